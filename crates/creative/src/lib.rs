@@ -3,6 +3,7 @@ use std::time::Duration;
 
 use fmc_client_api as fmc;
 use fmc_client_api::{math::BVec3, prelude::*};
+use serde::Serialize;
 
 // sqrt(2 * gravity * wanted height(1.4)) + some for air resistance
 const JUMP_VELOCITY: f32 = 9.0;
@@ -17,6 +18,13 @@ const GRAVITY: Vec3 = Vec3::new(0.0, -32.0, 0.0);
 // v_t = v_0 * at => (v_t - v_0) / a = t
 const JUMP_TIME: f32 = JUMP_VELOCITY * 1.7 / -GRAVITY.y;
 
+/// Data sent to the server, the variants must match the server's 'PluginPacket' enum.
+#[derive(Serialize)]
+enum ServerPacket {
+    /// The player started or stopped sneaking
+    Sneak(bool),
+}
+
 #[derive(Default)]
 struct Movement {
     acceleration: Vec3,
@@ -27,6 +35,7 @@ struct Movement {
     last_spacebar: Duration,
     last_jump: Duration,
     pressed_keys: HashSet<fmc::Key>,
+    is_sneaking: bool,
     // Cached delta time
     delta_time: Duration,
 }
@@ -53,6 +62,7 @@ impl fmc::Plugin for Movement {
         }
 
         self.accelerate();
+        self.report_sneaking();
         self.simulate_physics();
     }
 
@@ -71,6 +81,16 @@ impl fmc::Plugin for Movement {
 fmc::register_plugin!(Movement);
 
 impl Movement {
+    // Tells the server when the player starts or stops sneaking, sneaking changes what right
+    // clicking does. Shift moves the player down while flying, it isn't sneaking then.
+    fn report_sneaking(&mut self) {
+        let is_sneaking = !self.is_flying && self.pressed_keys.contains(&fmc::Key::Shift);
+        if is_sneaking != self.is_sneaking {
+            self.is_sneaking = is_sneaking;
+            fmc::send_server_data(bincode::serialize(&ServerPacket::Sneak(is_sneaking)).unwrap());
+        }
+    }
+
     fn accelerate(&mut self) {
         let camera_transform = fmc::get_camera_transform();
         let camera_forward = camera_transform.forward();
@@ -3,7 +3,7 @@ use std::time::Duration;
 
 use fmc_client_api as fmc;
 use fmc_client_api::{math::BVec3, prelude::*};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 // sqrt(2 * gravity * wanted height(1.4)) + some for air resistance
 const JUMP_VELOCITY: f32 = 9.0;
@@ -20,6 +20,13 @@ const JUMP_TIME: f32 = JUMP_VELOCITY * 1.7 / -GRAVITY.y;
 
 type ModelId = u32;
 
+/// Data sent to the server, the variants must match the server's 'PluginPacket' enum.
+#[derive(Serialize)]
+enum ServerPacket {
+    /// The player started or stopped sneaking
+    Sneak(bool),
+}
+
 #[derive(Default)]
 struct Movement {
     acceleration: Vec3,
@@ -28,6 +35,7 @@ struct Movement {
     is_grounded: BVec3,
     last_jump: Duration,
     pressed_keys: HashSet<fmc::Key>,
+    is_sneaking: bool,
     // Cached delta time
     delta_time: Duration,
     models: HashSet<ModelId>,
@@ -38,6 +46,7 @@ impl fmc::Plugin for Movement {
         self.delta_time = Duration::from_secs_f32(fmc::delta_time());
         self.last_jump += self.delta_time;
         self.accelerate();
+        self.report_sneaking();
         self.simulate_physics();
     }
 
@@ -79,6 +88,16 @@ impl fmc::Plugin for Movement {
 fmc::register_plugin!(Movement);
 
 impl Movement {
+    // Tells the server when the player starts or stops sneaking, sneaking changes what right
+    // clicking does.
+    fn report_sneaking(&mut self) {
+        let is_sneaking = self.pressed_keys.contains(&fmc::Key::Shift);
+        if is_sneaking != self.is_sneaking {
+            self.is_sneaking = is_sneaking;
+            fmc::send_server_data(bincode::serialize(&ServerPacket::Sneak(is_sneaking)).unwrap());
+        }
+    }
+
     fn accelerate(&mut self) {
         let camera_transform = fmc::get_camera_transform();
         let camera_forward = camera_transform.forward();
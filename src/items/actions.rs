@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use fmc::{
+    bevy::math::DVec3,
+    blocks::{BlockFace, Blocks},
+    items::{ItemId, Items, ITEM_CONFIG_PATH},
+    models::{Model, Models},
+    networking::Server,
+    physics::{Collider, Physics},
+    players::{Camera, Player, Target, Targets},
+    prelude::*,
+    protocol::messages,
+    world::{chunk::ChunkPosition, ChunkSubscriptions},
+};
+use serde::Deserialize;
+
+use crate::{
+    items::{DroppedItem, ItemRegistry, ItemUseSystems, ItemUses, PickupDelay},
+    players::{GameMode, Hotbar},
+};
+
+use super::bucket::register_buckets;
+
+// How long a player can go without right clicking before holding an item to use it restarts.
+const HOLD_TIMEOUT: Duration = Duration::from_millis(250);
+
+// Where the models and interfaces named by item actions must be found.
+const MODEL_PATH: &str = "./assets/client/textures/models/";
+const INTERFACE_PATH: &str = "./assets/client/interfaces/";
+
+pub struct ItemActionPlugin;
+impl Plugin for ItemActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EatEvent>()
+            // Items with a use defined in code take precedence.
+            .add_systems(Startup, register_item_actions.after(register_buckets))
+            .add_systems(
+                Update,
+                (use_items.after(ItemUseSystems), play_eating_sounds).chain(),
+            );
+    }
+}
+
+/// An action an item performs when used, declared by the "use" property of its item config,
+/// e.g. "use": { "action": "eat", "duration": 1.5 }
+#[derive(Component, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ItemAction {
+    /// The item is consumed, see 'EatEvent'
+    Eat,
+    /// Throw one of the item, it can be picked up again where it lands.
+    Throw { speed: f64 },
+    /// Places the model on top of the block the player is looking at. Placed models are not
+    /// saved, they are gone when the server restarts.
+    PlaceEntity { model: String },
+    /// Show the interface to the player.
+    OpenInterface { interface: String },
+}
+
+#[derive(Deserialize)]
+struct ItemUseJson {
+    #[serde(flatten)]
+    action: ItemAction,
+    /// Seconds before the item can be used again.
+    #[serde(default)]
+    cooldown: f32,
+    /// Seconds the player must hold right click for the item to be used.
+    #[serde(default)]
+    duration: f32,
+}
+
+/// Sent when a player has finished eating an item.
+#[derive(Event)]
+pub struct EatEvent {
+    pub player_entity: Entity,
+}
+
+// The item an entity with an 'ItemAction' belongs to.
+#[derive(Component)]
+struct ActionItem(ItemId);
+
+/// Limits how often an item can be used, and how long it must be held before it is. Added to an
+/// entity in the 'ItemRegistry' it is checked before its 'ItemUses' are given the player.
+#[derive(Component)]
+pub struct ItemUseTiming {
+    cooldown: Duration,
+    duration: Duration,
+    players: HashMap<Entity, UseProgress>,
+}
+
+struct UseProgress {
+    started: Instant,
+    last_use: Instant,
+    ready_at: Instant,
+}
+
+impl ItemUseTiming {
+    pub fn new(cooldown: Duration, duration: Duration) -> Self {
+        Self {
+            cooldown,
+            duration,
+            players: HashMap::new(),
+        }
+    }
+
+    /// Register a right click by the player, returns true when the item should be used.
+    pub fn try_use(&mut self, player_entity: Entity) -> bool {
+        let now = Instant::now();
+        self.players.retain(|_, progress| {
+            now < progress.ready_at || now - progress.last_use < HOLD_TIMEOUT
+        });
+
+        let progress = self.players.entry(player_entity).or_insert(UseProgress {
+            started: now,
+            last_use: now,
+            ready_at: now,
+        });
+
+        if now < progress.ready_at {
+            return false;
+        }
+
+        if now - progress.last_use > HOLD_TIMEOUT {
+            progress.started = now;
+        }
+        progress.last_use = now;
+
+        if now - progress.started < self.duration {
+            return false;
+        }
+
+        progress.started = now;
+        progress.ready_at = now + self.cooldown;
+        true
+    }
+}
+
+fn register_item_actions(
+    mut commands: Commands,
+    items: Res<Items>,
+    mut item_registry: ResMut<ItemRegistry>,
+) {
+    let directory = match std::fs::read_dir(ITEM_CONFIG_PATH) {
+        Ok(dir) => dir,
+        Err(e) => panic!(
+            "Failed to read the item config directory at '{}'\nError: {}",
+            ITEM_CONFIG_PATH, e
+        ),
+    };
+
+    for dir_entry in directory {
+        let file_path = dir_entry.unwrap().path();
+        let Some(item_id) = file_path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .and_then(|name| items.get_id(name))
+        else {
+            continue;
+        };
+
+        let item_config = items.get_config(&item_id);
+        let Some(json) = item_config.properties.get("use") else {
+            continue;
+        };
+
+        let item_use: ItemUseJson = match serde_json::from_value(json.clone()) {
+            Ok(item_use) => item_use,
+            Err(e) => panic!(
+                "Startup failed while reading the use action of the item '{}' at '{}'\nError: {}",
+                item_config.name,
+                file_path.display(),
+                e
+            ),
+        };
+
+        let missing = match &item_use.action {
+            ItemAction::PlaceEntity { model } if !asset_exists(MODEL_PATH, model) => {
+                Some(("model", model, MODEL_PATH))
+            }
+            // Interfaces are opened by their path, e.g. "furnace/progress", the root of it is
+            // the interface file.
+            ItemAction::OpenInterface { interface }
+                if !asset_exists(INTERFACE_PATH, interface.split('/').next().unwrap()) =>
+            {
+                Some(("interface", interface, INTERFACE_PATH))
+            }
+            _ => None,
+        };
+
+        if let Some((kind, name, path)) = missing {
+            panic!(
+                "Startup failed while reading the use action of the item '{}' at '{}'. It uses \
+                the {} '{}', but none by that name exists. Make sure it is present at '{}'",
+                item_config.name,
+                file_path.display(),
+                kind,
+                name,
+                path
+            );
+        }
+
+        // Registered by code, see 'register_buckets'
+        if item_registry.contains_key(&item_id) {
+            continue;
+        }
+
+        let entity = commands
+            .spawn((
+                ItemUses::default(),
+                ItemUseTiming::new(
+                    Duration::from_secs_f32(item_use.cooldown),
+                    Duration::from_secs_f32(item_use.duration),
+                ),
+                item_use.action,
+                ActionItem(item_id),
+            ))
+            .id();
+        item_registry.insert(item_id, entity);
+    }
+}
+
+// If a file with the name exists in the directory, the extension is ignored.
+fn asset_exists(directory: &str, name: &str) -> bool {
+    let Ok(directory) = std::fs::read_dir(directory) else {
+        return false;
+    };
+
+    directory.filter_map(|entry| entry.ok()).any(|entry| {
+        entry
+            .path()
+            .file_stem()
+            .is_some_and(|file_stem| file_stem == name)
+    })
+}
+
+fn use_items(
+    mut commands: Commands,
+    net: Res<Server>,
+    models: Res<Models>,
+    mut item_actions: Query<(&mut ItemUses, &ItemAction, &ActionItem)>,
+    mut players: Query<(&mut Hotbar, &GameMode, &Targets, &Camera, &GlobalTransform), With<Player>>,
+    mut eat_events: EventWriter<EatEvent>,
+) {
+    for (mut uses, action, action_item) in item_actions.iter_mut() {
+        for player_entity in uses.read() {
+            let Ok((mut hotbar, game_mode, targets, camera, transform)) =
+                players.get_mut(player_entity)
+            else {
+                continue;
+            };
+
+            // The held item may have changed since the use was registered.
+            if hotbar
+                .held_item_stack()
+                .item()
+                .is_none_or(|item| item.id != action_item.0)
+            {
+                continue;
+            }
+
+            let camera_position = transform.translation() + camera.translation;
+
+            match action {
+                ItemAction::Eat => {
+                    hotbar.held_item_stack_mut().take(1);
+                    eat_events.send(EatEvent { player_entity });
+                }
+                ItemAction::Throw { speed } => {
+                    let item_stack = hotbar.held_item_stack_mut().take(1);
+                    if item_stack.is_empty() {
+                        continue;
+                    }
+                    commands.spawn((
+                        DroppedItem::new(item_stack),
+                        PickupDelay::new(player_entity),
+                        Transform::from_translation(camera_position - DVec3::Y * 0.3),
+                        Physics {
+                            enabled: true,
+                            velocity: camera.forward() * *speed,
+                            ..default()
+                        },
+                    ));
+                }
+                ItemAction::PlaceEntity { model } => {
+                    let Some(Target::Block {
+                        block_position,
+                        block_face: BlockFace::Top,
+                        ..
+                    }) = targets.get_first_block(|block_id| {
+                        Blocks::get().get_config(block_id).hardness.is_some()
+                    })
+                    else {
+                        continue;
+                    };
+
+                    if *game_mode != GameMode::Creative {
+                        hotbar.held_item_stack_mut().take(1);
+                    }
+
+                    let model_config = models.get_by_name(model);
+                    let position = block_position.as_dvec3() + DVec3::new(0.5, 1.0, 0.5);
+                    commands.spawn((
+                        Model::Asset(model_config.id),
+                        Collider::Aabb(model_config.aabb.clone()),
+                        Transform::from_translation(
+                            position - model_config.aabb.center
+                                + DVec3::Y * model_config.aabb.half_extents.y,
+                        ),
+                    ));
+                }
+                ItemAction::OpenInterface { interface } => {
+                    net.send_one(
+                        player_entity,
+                        messages::InterfaceVisibilityUpdate {
+                            interface_path: interface.clone(),
+                            visible: true,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn play_eating_sounds(
+    net: Res<Server>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    players: Query<&GlobalTransform, With<Player>>,
+    mut eat_events: EventReader<EatEvent>,
+) {
+    for eat_event in eat_events.read() {
+        let Ok(transform) = players.get(eat_event.player_entity) else {
+            continue;
+        };
+
+        let position = transform.translation();
+        let Some(subscribers) = chunk_subscriptions.get_subscribers(&ChunkPosition::from(position))
+        else {
+            continue;
+        };
+
+        net.send_many(
+            subscribers,
+            messages::Sound {
+                position: Some(position),
+                volume: 0.5,
+                speed: 1.0,
+                sound: "eat.ogg".to_owned(),
+            },
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use fmc::{
+    blocks::Blocks,
+    items::{ItemId, ItemStack, Items},
+    players::{Player, Target, Targets},
+    prelude::*,
+    world::{BlockUpdate, WorldMap},
+};
+
+use crate::{
+    items::{DroppedItem, ItemRegistry, ItemUseSystems, ItemUses},
+    players::{insert_item_stack, Hotbar, Inventory},
+    world::blocks::Fluids,
+};
+
+pub struct BucketPlugin;
+impl Plugin for BucketPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, register_buckets)
+            .add_systems(Update, use_buckets.after(ItemUseSystems));
+    }
+}
+
+// Buckets filled with a fluid are named after it, e.g. "water_bucket". Fluids without a bucket
+// item can't be picked up.
+#[derive(Component)]
+struct Bucket {
+    item_id: ItemId,
+    // Name of the fluid in the bucket, None if it is empty.
+    fluid: Option<&'static str>,
+}
+
+pub(super) fn register_buckets(
+    mut commands: Commands,
+    items: Res<Items>,
+    mut item_registry: ResMut<ItemRegistry>,
+) {
+    for (name, fluid) in [("bucket", None), ("water_bucket", Some("water"))] {
+        let item_id = items.get_id(name).unwrap();
+        let entity = commands
+            .spawn((ItemUses::default(), Bucket { item_id, fluid }))
+            .id();
+        item_registry.insert(item_id, entity);
+    }
+}
+
+fn use_buckets(
+    mut commands: Commands,
+    items: Res<Items>,
+    world_map: Res<WorldMap>,
+    fluids: Res<Fluids>,
+    mut bucket_query: Query<(&mut ItemUses, &Bucket)>,
+    mut player_query: Query<
+        (&mut Hotbar, &mut Inventory, &Targets, &GlobalTransform),
+        With<Player>,
+    >,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+
+    for (mut uses, bucket) in bucket_query.iter_mut() {
+        for player_entity in uses.read() {
+            let Ok((mut hotbar, mut inventory, targets, transform)) =
+                player_query.get_mut(player_entity)
+            else {
+                continue;
+            };
+
+            // The held item may have changed since the use was registered.
+            if hotbar
+                .held_item_stack()
+                .item()
+                .is_none_or(|item| item.id != bucket.item_id)
+            {
+                continue;
+            }
+
+            match bucket.fluid {
+                None => {
+                    // Sources can only be picked up if there's nothing solid in front of them.
+                    let Some(Target::Block { block_position, .. }) =
+                        targets.get_first_block(|block_id| {
+                            blocks.get_config(block_id).hardness.is_some()
+                                || fluids.get_by_source(&(*block_id, None)).is_some()
+                        })
+                    else {
+                        continue;
+                    };
+
+                    let block_position = *block_position;
+                    let Some(block_id) = world_map.get_block(block_position) else {
+                        continue;
+                    };
+                    let block_state = world_map.get_block_state(block_position);
+                    let Some(fluid) = fluids.get_by_source(&(block_id, block_state)) else {
+                        continue;
+                    };
+                    let Some(filled_bucket_id) = items.get_id(&format!("{}_bucket", fluid.name))
+                    else {
+                        continue;
+                    };
+
+                    block_updates.send(BlockUpdate::Replace {
+                        position: block_position,
+                        block_id: air,
+                        block_state: None,
+                        block_data: None,
+                    });
+
+                    let mut filled_bucket = ItemStack::new(items.get_config(&filled_bucket_id), 1);
+
+                    let held_item_stack = hotbar.held_item_stack_mut();
+                    if held_item_stack.size() == 1 {
+                        *held_item_stack = filled_bucket;
+                        continue;
+                    }
+
+                    held_item_stack.take(1);
+
+                    insert_item_stack(&mut hotbar, &mut inventory, &mut filled_bucket);
+                    if !filled_bucket.is_empty() {
+                        commands.spawn((
+                            DroppedItem::new(filled_bucket),
+                            Transform::from_translation(transform.translation()),
+                        ));
+                    }
+                }
+                Some(fluid_name) => {
+                    let Some(fluid) = fluids.get(fluid_name) else {
+                        continue;
+                    };
+
+                    let Some(Target::Block {
+                        block_position,
+                        block_id,
+                        block_face,
+                        ..
+                    }) = targets
+                        .get_first_block(|block_id| blocks.get_config(block_id).hardness.is_some())
+                    else {
+                        continue;
+                    };
+
+                    let position = if blocks.get_config(block_id).replaceable {
+                        *block_position
+                    } else {
+                        block_face.shift_position(*block_position)
+                    };
+
+                    let Some(replaced_block) = world_map.get_block(position) else {
+                        continue;
+                    };
+                    if !blocks.get_config(&replaced_block).replaceable {
+                        continue;
+                    }
+
+                    // Placed as a block update so that 'schedule_fluid_ticks' makes it flow.
+                    let (block_id, block_state) = fluid.source_block();
+                    block_updates.send(BlockUpdate::Replace {
+                        position,
+                        block_id,
+                        block_state,
+                        block_data: None,
+                    });
+
+                    let bucket_config = items.get_config(&items.get_id("bucket").unwrap());
+                    *hotbar.held_item_stack_mut() = ItemStack::new(bucket_config, 1);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use std::{collections::HashMap, time::Duration};
+
+use fmc::{
+    items::{ItemId, ItemStack, Items, ITEM_CONFIG_PATH},
+    networking::{NetworkMessage, Server},
+    prelude::*,
+    protocol::messages,
+};
+use serde::Deserialize;
+
+use crate::chat::{CHAT_FONT_SIZE, CHAT_TEXT_COLOR};
+
+pub const RECIPE_PATH: &str = "./assets/server/recipes/";
+
+pub struct CraftingPlugin;
+impl Plugin for CraftingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_recipes)
+            .add_systems(Update, recipe_book);
+    }
+}
+
+/// A recipe file, named after what it makes. Mods can add recipes by placing files in their own
+/// recipe directory, or replace one by using the same file name.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum RecipeJson {
+    /// The ingredients must be placed in the same pattern, anywhere in the grid. Each character
+    /// of the pattern is looked up in the key, spaces are empty slots.
+    Shaped {
+        pattern: Vec<String>,
+        key: HashMap<char, String>,
+        output: OutputJson,
+    },
+    /// The ingredients can be placed in any slot.
+    Shapeless {
+        ingredients: Vec<String>,
+        output: OutputJson,
+    },
+    /// Made in a furnace from a single item, 'cook_time' is in seconds.
+    Smelting {
+        input: String,
+        cook_time: f32,
+        output: OutputJson,
+    },
+}
+
+#[derive(Deserialize)]
+struct OutputJson {
+    item: String,
+    #[serde(default = "default_count")]
+    count: u32,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+enum Ingredients {
+    Shaped {
+        width: usize,
+        height: usize,
+        items: Vec<Option<ItemId>>,
+    },
+    // Sorted so it can be compared directly
+    Shapeless(Vec<ItemId>),
+}
+
+pub struct Recipe {
+    ingredients: Ingredients,
+    output: ItemId,
+    // Name of the output item, as it is used in recipe files and '/recipes <item>'
+    output_name: String,
+    count: u32,
+}
+
+impl Recipe {
+    fn matches(&self, grid: &CraftingGridView) -> bool {
+        match &self.ingredients {
+            Ingredients::Shaped {
+                width,
+                height,
+                items,
+            } => *width == grid.width && *height == grid.height && *items == grid.items,
+            Ingredients::Shapeless(ingredients) => {
+                let mut items: Vec<ItemId> = grid.items.iter().flatten().copied().collect();
+                items.sort();
+                *ingredients == items
+            }
+        }
+    }
+}
+
+// The smallest part of a crafting grid that contains all of its items.
+struct CraftingGridView {
+    width: usize,
+    height: usize,
+    items: Vec<Option<ItemId>>,
+}
+
+impl CraftingGridView {
+    fn new(grid: &[ItemStack], grid_width: usize) -> Self {
+        let item_ids = grid
+            .iter()
+            .map(|item_stack| item_stack.item().map(|item| item.id))
+            .collect();
+        Self::trim(item_ids, grid_width)
+    }
+
+    fn trim(item_ids: Vec<Option<ItemId>>, grid_width: usize) -> Self {
+        let grid_height = item_ids.len() / grid_width;
+        let item_id = |x: usize, y: usize| item_ids[y * grid_width + x];
+
+        let filled: Vec<(usize, usize)> = (0..grid_height)
+            .flat_map(|y| (0..grid_width).map(move |x| (x, y)))
+            .filter(|(x, y)| item_id(*x, *y).is_some())
+            .collect();
+
+        let Some(min_x) = filled.iter().map(|(x, _)| *x).min() else {
+            return Self {
+                width: 0,
+                height: 0,
+                items: Vec::new(),
+            };
+        };
+        let max_x = filled.iter().map(|(x, _)| *x).max().unwrap();
+        let min_y = filled.iter().map(|(_, y)| *y).min().unwrap();
+        let max_y = filled.iter().map(|(_, y)| *y).max().unwrap();
+
+        let mut items = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                items.push(item_id(x, y));
+            }
+        }
+
+        Self {
+            width: max_x - min_x + 1,
+            height: max_y - min_y + 1,
+            items,
+        }
+    }
+}
+
+pub struct SmeltingRecipe {
+    input: ItemId,
+    output: ItemId,
+    output_name: String,
+    count: u32,
+    cook_time: Duration,
+}
+
+impl SmeltingRecipe {
+    pub fn output(&self, items: &Items) -> ItemStack {
+        ItemStack::new(items.get_config(&self.output), self.count)
+    }
+
+    pub fn cook_time(&self) -> Duration {
+        self.cook_time
+    }
+}
+
+#[derive(Resource)]
+pub struct Recipes {
+    recipes: Vec<Recipe>,
+    smelting: HashMap<ItemId, SmeltingRecipe>,
+}
+
+impl Recipes {
+    /// The recipe for smelting the item, if it can be smelted.
+    pub fn get_smelting(&self, input: &ItemId) -> Option<&SmeltingRecipe> {
+        self.smelting.get(input)
+    }
+
+    fn find(&self, grid: &[ItemStack], grid_width: usize) -> Option<&Recipe> {
+        let view = CraftingGridView::new(grid, grid_width);
+        if view.items.is_empty() {
+            return None;
+        }
+        self.recipes.iter().find(|recipe| recipe.matches(&view))
+    }
+
+    /// The item stack the items in the grid would make.
+    pub fn get_output(&self, items: &Items, grid: &[ItemStack], grid_width: usize) -> ItemStack {
+        match self.find(grid, grid_width) {
+            Some(recipe) => ItemStack::new(items.get_config(&recipe.output), recipe.count),
+            None => ItemStack::default(),
+        }
+    }
+
+    /// Use up one of each ingredient in the grid and return what they made. Returns None if the
+    /// items do not match any recipe.
+    pub fn craft(
+        &self,
+        items: &Items,
+        grid: &mut [ItemStack],
+        grid_width: usize,
+    ) -> Option<ItemStack> {
+        let recipe = self.find(grid, grid_width)?;
+
+        for item_stack in grid.iter_mut() {
+            if !item_stack.is_empty() {
+                item_stack.take(1);
+            }
+        }
+
+        Some(ItemStack::new(
+            items.get_config(&recipe.output),
+            recipe.count,
+        ))
+    }
+}
+
+fn load_recipes(mut commands: Commands, items: Res<Items>) {
+    let mut recipes = Recipes {
+        recipes: Vec::new(),
+        smelting: HashMap::new(),
+    };
+
+    // The file each smelting recipe was read from, to tell which ones conflict.
+    let mut smelting_files: HashMap<ItemId, String> = HashMap::new();
+
+    let directory = match std::fs::read_dir(RECIPE_PATH) {
+        Ok(dir) => dir,
+        Err(e) => panic!(
+            "Failed to read the recipe directory at '{}'\nError: {}",
+            RECIPE_PATH, e
+        ),
+    };
+
+    for dir_entry in directory {
+        let file_path = dir_entry.unwrap().path();
+        let Some(recipe_name) = file_path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        let file = std::fs::File::open(&file_path).unwrap();
+        let json: RecipeJson = match serde_json::from_reader(file) {
+            Ok(json) => json,
+            Err(e) => panic!(
+                "Failed to read the recipe at '{}'\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        let item_id = |item_name: &str| -> ItemId {
+            match items.get_id(item_name) {
+                Some(item_id) => item_id,
+                None => panic!(
+                    "Startup failed while validating the recipes. The recipe '{}' references an \
+                    item with the name '{}', but no item by that name exists. Make sure an item \
+                    by the same name is present at '{}'",
+                    recipe_name, item_name, ITEM_CONFIG_PATH
+                ),
+            }
+        };
+
+        let (ingredients, output) = match json {
+            RecipeJson::Shaped {
+                pattern,
+                key,
+                output,
+            } => {
+                let width = pattern
+                    .iter()
+                    .map(|row| row.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                let height = pattern.len();
+                if width == 0 || width > 3 || height > 3 {
+                    panic!(
+                        "Startup failed while validating the recipes. The pattern of the recipe \
+                        '{}' must be between 1x1 and 3x3.",
+                        recipe_name
+                    );
+                }
+
+                let mut item_ids = Vec::with_capacity(width * height);
+                for row in pattern.iter() {
+                    let mut chars = row.chars();
+                    for _ in 0..width {
+                        match chars.next() {
+                            None | Some(' ') => item_ids.push(None),
+                            Some(c) => match key.get(&c) {
+                                Some(item_name) => item_ids.push(Some(item_id(item_name))),
+                                None => panic!(
+                                    "Startup failed while validating the recipes. The pattern \
+                                    of the recipe '{}' uses the character '{}', but it is not \
+                                    in the key.",
+                                    recipe_name, c
+                                ),
+                            },
+                        }
+                    }
+                }
+
+                // Trim empty rows and columns so it can be compared to the trimmed grid.
+                let view = CraftingGridView::trim(item_ids, width);
+
+                (
+                    Ingredients::Shaped {
+                        width: view.width,
+                        height: view.height,
+                        items: view.items,
+                    },
+                    output,
+                )
+            }
+            RecipeJson::Shapeless {
+                ingredients,
+                output,
+            } => {
+                if ingredients.is_empty() || ingredients.len() > 9 {
+                    panic!(
+                        "Startup failed while validating the recipes. The recipe '{}' must have \
+                        between 1 and 9 ingredients.",
+                        recipe_name
+                    );
+                }
+                let mut ingredients: Vec<ItemId> =
+                    ingredients.iter().map(|name| item_id(name)).collect();
+                ingredients.sort();
+                (Ingredients::Shapeless(ingredients), output)
+            }
+            RecipeJson::Smelting {
+                input,
+                cook_time,
+                output,
+            } => {
+                if cook_time <= 0.0 {
+                    panic!(
+                        "Startup failed while validating the recipes. The cook time of the \
+                        recipe '{}' must be more than zero.",
+                        recipe_name
+                    );
+                }
+                let input_id = item_id(&input);
+                if let Some(other_recipe) = smelting_files.insert(input_id, recipe_name.to_owned())
+                {
+                    panic!(
+                        "Startup failed while validating the recipes. The recipes '{}' and '{}' \
+                        both smelt the item '{}', an item can only be smelted into one thing.",
+                        other_recipe, recipe_name, input
+                    );
+                }
+                recipes.smelting.insert(
+                    input_id,
+                    SmeltingRecipe {
+                        input: input_id,
+                        output: item_id(&output.item),
+                        output_name: output.item,
+                        count: output.count,
+                        cook_time: Duration::from_secs_f32(cook_time),
+                    },
+                );
+                continue;
+            }
+        };
+
+        recipes.recipes.push(Recipe {
+            ingredients,
+            output: item_id(&output.item),
+            output_name: output.item,
+            count: output.count,
+        });
+    }
+
+    commands.insert_resource(recipes);
+}
+
+// '/recipes' lists the items that can be crafted, '/recipes <item>' shows how to craft the item.
+fn recipe_book(
+    net: Res<Server>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    mut chat_messages: EventReader<NetworkMessage<messages::InterfaceTextInput>>,
+) {
+    for chat_message in chat_messages.read() {
+        if &chat_message.interface_path != "chat/input" {
+            continue;
+        }
+
+        let mut arguments = chat_message.text.split_whitespace();
+        if arguments.next() != Some("/recipes") {
+            continue;
+        }
+
+        let mut lines = Vec::new();
+
+        match arguments.next().map(|name| (name, items.get_id(name))) {
+            None => {
+                let mut names: Vec<&str> = recipes
+                    .recipes
+                    .iter()
+                    .map(|recipe| recipe.output_name.as_str())
+                    .chain(
+                        recipes
+                            .smelting
+                            .values()
+                            .map(|recipe| recipe.output_name.as_str()),
+                    )
+                    .collect();
+                names.sort();
+                names.dedup();
+                lines.push(format!("Recipes: {}", names.join(", ")));
+            }
+            Some((name, None)) => {
+                lines.push(format!("There is no item called '{}'", name));
+            }
+            Some((name, Some(output_id))) => {
+                for recipe in recipes
+                    .recipes
+                    .iter()
+                    .filter(|recipe| recipe.output == output_id)
+                {
+                    let item_name = |item_id: &ItemId| items.get_config(item_id).name.clone();
+                    let output = format!("{} {}", recipe.count, item_name(&recipe.output));
+                    match &recipe.ingredients {
+                        Ingredients::Shaped { width, items, .. } => {
+                            lines.push(format!("{}, shaped:", output));
+                            for row in items.chunks(*width) {
+                                let row: Vec<String> = row
+                                    .iter()
+                                    .map(|item_id| match item_id {
+                                        Some(item_id) => item_name(item_id),
+                                        None => "-".to_owned(),
+                                    })
+                                    .collect();
+                                lines.push(format!("  [{}]", row.join(", ")));
+                            }
+                        }
+                        Ingredients::Shapeless(ingredients) => {
+                            let ingredients: Vec<String> =
+                                ingredients.iter().map(item_name).collect();
+                            lines.push(format!(
+                                "{}, shapeless: {}",
+                                output,
+                                ingredients.join(", ")
+                            ));
+                        }
+                    }
+                }
+
+                for recipe in recipes
+                    .smelting
+                    .values()
+                    .filter(|recipe| recipe.output == output_id)
+                {
+                    lines.push(format!(
+                        "{} {}, smelted from {} in {} seconds",
+                        recipe.count,
+                        items.get_config(&recipe.output).name,
+                        items.get_config(&recipe.input).name,
+                        recipe.cook_time.as_secs_f32()
+                    ));
+                }
+
+                if lines.is_empty() {
+                    lines.push(format!("There is no recipe for '{}'", name));
+                }
+            }
+        }
+
+        for text in lines {
+            net.send_one(
+                chat_message.player_entity,
+                messages::InterfaceTextUpdate {
+                    interface_path: "chat/history".to_owned(),
+                    index: i32::MAX,
+                    text,
+                    font_size: CHAT_FONT_SIZE,
+                    color: CHAT_TEXT_COLOR.to_owned(),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: Option<ItemId> = Some(1);
+    const STICK: Option<ItemId> = Some(2);
+
+    fn shaped(width: usize, height: usize, items: Vec<Option<ItemId>>) -> Recipe {
+        Recipe {
+            ingredients: Ingredients::Shaped {
+                width,
+                height,
+                items,
+            },
+            output: 3,
+            output_name: "pickaxe".to_owned(),
+            count: 1,
+        }
+    }
+
+    fn shapeless(mut ingredients: Vec<ItemId>) -> Recipe {
+        ingredients.sort();
+        Recipe {
+            ingredients: Ingredients::Shapeless(ingredients),
+            output: 3,
+            output_name: "pickaxe".to_owned(),
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn trim_removes_empty_rows_and_columns() {
+        #[rustfmt::skip]
+        let grid = vec![
+            None, None,  None,
+            None, STONE, STONE,
+            None, None,  STICK,
+        ];
+        let view = CraftingGridView::trim(grid, 3);
+
+        assert_eq!(view.width, 2);
+        assert_eq!(view.height, 2);
+        assert_eq!(view.items, vec![STONE, STONE, None, STICK]);
+    }
+
+    #[test]
+    fn trim_keeps_gaps_between_items() {
+        #[rustfmt::skip]
+        let grid = vec![
+            STONE, None, STONE,
+            None,  None, None,
+            None,  None, None,
+        ];
+        let view = CraftingGridView::trim(grid, 3);
+
+        assert_eq!(view.width, 3);
+        assert_eq!(view.height, 1);
+        assert_eq!(view.items, vec![STONE, None, STONE]);
+    }
+
+    #[test]
+    fn trim_empty_grid() {
+        let view = CraftingGridView::trim(vec![None; 4], 2);
+
+        assert_eq!(view.width, 0);
+        assert_eq!(view.height, 0);
+        assert!(view.items.is_empty());
+    }
+
+    #[test]
+    fn shaped_recipe_matches_anywhere_in_grid() {
+        let recipe = shaped(1, 2, vec![STONE, STICK]);
+
+        #[rustfmt::skip]
+        let top_left = vec![
+            STONE, None, None,
+            STICK, None, None,
+            None,  None, None,
+        ];
+        #[rustfmt::skip]
+        let bottom_right = vec![
+            None, None, None,
+            None, None, STONE,
+            None, None, STICK,
+        ];
+
+        assert!(recipe.matches(&CraftingGridView::trim(top_left, 3)));
+        assert!(recipe.matches(&CraftingGridView::trim(bottom_right, 3)));
+    }
+
+    #[test]
+    fn shaped_recipe_requires_the_pattern() {
+        let recipe = shaped(1, 2, vec![STONE, STICK]);
+
+        let upside_down = CraftingGridView::trim(vec![STICK, None, STONE, None], 2);
+        let sideways = CraftingGridView::trim(vec![STONE, STICK, None, None], 2);
+        let extra = CraftingGridView::trim(vec![STONE, STONE, STICK, None], 2);
+
+        assert!(!recipe.matches(&upside_down));
+        assert!(!recipe.matches(&sideways));
+        assert!(!recipe.matches(&extra));
+    }
+
+    #[test]
+    fn shapeless_recipe_matches_any_placement() {
+        let recipe = shapeless(vec![2, 1]);
+
+        let together = CraftingGridView::trim(vec![STONE, STICK, None, None], 2);
+        let apart = CraftingGridView::trim(vec![STICK, None, None, STONE], 2);
+
+        assert!(recipe.matches(&together));
+        assert!(recipe.matches(&apart));
+    }
+
+    #[test]
+    fn shapeless_recipe_requires_exact_ingredients() {
+        let recipe = shapeless(vec![2, 1]);
+
+        let missing = CraftingGridView::trim(vec![STONE, None, None, None], 2);
+        let extra = CraftingGridView::trim(vec![STONE, STICK, STICK, None], 2);
+
+        assert!(!recipe.matches(&missing));
+        assert!(!recipe.matches(&extra));
+    }
+}
@@ -1,19 +1,57 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
 use fmc::{
-    bevy::math::DVec3,
+    bevy::{
+        diagnostic::{
+            Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, RegisterDiagnostic,
+        },
+        math::DVec3,
+        time::common_conditions::on_timer,
+    },
     items::{ItemStack, Items},
     models::{Model, ModelMap, Models},
+    networking::Server,
     physics::{Collider, Physics},
+    players::Player,
     prelude::*,
+    protocol::messages,
     utils::Rng,
-    world::chunk::ChunkPosition,
+    world::{chunk::ChunkPosition, ChunkSubscriptions},
+};
+
+use crate::{
+    players::{has_room_for, insert_item_stack, Hotbar, Inventory},
+    settings::Settings,
 };
 
-use crate::players::Hotbar;
+/// Number of dropped items in the world
+pub const DROPPED_ITEM_COUNT: DiagnosticPath = DiagnosticPath::const_new("dropped_items");
+
+// Items closer than this to a player are picked up, items further away but within the pickup
+// radius are pulled towards the player.
+const PICKUP_DISTANCE: f64 = 1.0;
+const ATTRACTION_SPEED: f64 = 8.0;
+
+// Items of the same kind closer than this are merged.
+const MERGE_DISTANCE: f64 = 1.0;
 
 pub struct DroppedItemsPlugin;
 impl Plugin for DroppedItemsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, pick_up_items)
+        app.register_diagnostic(Diagnostic::new(DROPPED_ITEM_COUNT))
+            .add_systems(
+                Update,
+                (
+                    (tick_pickup_delays, pick_up_items).chain(),
+                    animate_pickups,
+                    despawn_old_items,
+                    merge_items.run_if(on_timer(Duration::from_secs(1))),
+                    log_dropped_item_count.run_if(on_timer(Duration::from_secs(60))),
+                ),
+            )
             .add_systems(Update, manage_item_models.in_set(DropItems));
     }
 }
@@ -24,15 +62,65 @@ pub struct DropItems;
 
 // An item that is dropped on the ground.
 #[derive(Component, Deref, DerefMut)]
-#[require(Transform)]
+#[require(Transform, DroppedItemAge)]
 pub struct DroppedItem(ItemStack);
 
+// How long the item has been on the ground.
+#[derive(Component, Default)]
+struct DroppedItemAge(Duration);
+
 impl DroppedItem {
     pub fn new(item_stack: ItemStack) -> Self {
         Self(item_stack)
     }
 }
 
+/// Stops the player that threw the item from picking it back up until the timer is finished.
+#[derive(Component)]
+pub struct PickupDelay {
+    player_entity: Entity,
+    timer: Timer,
+}
+
+impl PickupDelay {
+    pub fn new(player_entity: Entity) -> Self {
+        Self {
+            player_entity,
+            timer: Timer::from_seconds(1.5, TimerMode::Once),
+        }
+    }
+}
+
+fn tick_pickup_delays(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pickup_delays: Query<(Entity, &mut PickupDelay)>,
+) {
+    for (entity, mut pickup_delay) in pickup_delays.iter_mut() {
+        if pickup_delay.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<PickupDelay>();
+        }
+    }
+}
+
+// Items that have been picked up fly into the player before they are removed.
+#[derive(Component)]
+struct PickupAnimation {
+    player_entity: Entity,
+    start: DVec3,
+    timer: Timer,
+}
+
+impl PickupAnimation {
+    fn new(player_entity: Entity, start: DVec3) -> Self {
+        Self {
+            player_entity,
+            start,
+            timer: Timer::from_seconds(0.15, TimerMode::Once),
+        }
+    }
+}
+
 fn manage_item_models(
     mut commands: Commands,
     models: Res<Models>,
@@ -84,74 +172,247 @@ fn manage_item_models(
 
 fn pick_up_items(
     mut commands: Commands,
+    net: Res<Server>,
+    settings: Res<Settings>,
     model_map: Res<ModelMap>,
-    mut players: Query<(&GlobalTransform, &mut Hotbar), Changed<GlobalTransform>>,
-    mut dropped_items: Query<(Entity, &mut DroppedItem, &Transform)>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    mut players: Query<(Entity, &GlobalTransform, &mut Hotbar, &mut Inventory), With<Player>>,
+    mut dropped_items: Query<(
+        &mut DroppedItem,
+        &Transform,
+        &mut Physics,
+        Option<&PickupDelay>,
+    )>,
+) {
+    let radius = settings.item_pickup_radius;
+
+    for (player_entity, player_transform, mut player_hotbar, mut player_inventory) in
+        players.iter_mut()
+    {
+        // Items are pulled towards the middle of the player's body.
+        let player_position = player_transform.translation() + DVec3::Y * 0.5;
+
+        // All chunks the pickup radius reaches into.
+        let mut chunk_positions = HashSet::new();
+        for x in [-radius, 0.0, radius] {
+            for y in [-radius, 0.0, radius] {
+                for z in [-radius, 0.0, radius] {
+                    chunk_positions
+                        .insert(ChunkPosition::from(player_position + DVec3::new(x, y, z)));
+                }
+            }
+        }
+
+        for chunk_position in chunk_positions {
+            let Some(item_entities) = model_map.get_entities(&chunk_position) else {
+                continue;
+            };
+
+            for item_entity in item_entities.iter() {
+                let Ok((mut dropped_item, transform, mut physics, pickup_delay)) =
+                    dropped_items.get_mut(*item_entity)
+                else {
+                    continue;
+                };
+
+                // Already picked up by another player this frame, it is despawned once the
+                // commands are applied.
+                if dropped_item.is_empty() {
+                    continue;
+                }
+
+                if pickup_delay.is_some_and(|delay| delay.player_entity == player_entity) {
+                    continue;
+                }
+
+                let offset = player_position - transform.translation;
+                if offset.length_squared() > radius * radius {
+                    continue;
+                }
+
+                // First test that the item can be picked up. This is to avoid triggering
+                // change detection for the hotbar. If detection is triggered, it will send
+                // an interface update to the client. Can't pick up = spam
+                if !has_room_for(&player_hotbar, &player_inventory, &dropped_item) {
+                    continue;
+                }
+
+                if offset.length_squared() > PICKUP_DISTANCE * PICKUP_DISTANCE {
+                    physics.velocity = offset.normalize() * ATTRACTION_SPEED;
+                    continue;
+                }
+
+                // Fills the hotbar first, overflow goes into the inventory.
+                insert_item_stack(&mut player_hotbar, &mut player_inventory, &mut dropped_item);
+
+                if let Some(subscribers) = chunk_subscriptions.get_subscribers(&chunk_position) {
+                    net.send_many(
+                        subscribers,
+                        messages::Sound {
+                            position: Some(transform.translation),
+                            volume: 0.3,
+                            speed: 2.0,
+                            sound: "item_pickup.ogg".to_owned(),
+                        },
+                    );
+                }
+
+                // The model's movement towards the player is sent to the chunk's subscribers
+                // like any other model transform before it is despawned.
+                if dropped_item.is_empty() {
+                    commands
+                        .entity(*item_entity)
+                        .remove::<(DroppedItem, Physics, Collider)>()
+                        .insert(PickupAnimation::new(player_entity, transform.translation));
+                }
+            }
+        }
+    }
+}
+
+fn animate_pickups(
+    mut commands: Commands,
+    time: Res<Time>,
+    players: Query<&GlobalTransform, With<Player>>,
+    mut animations: Query<(Entity, &mut Transform, &mut PickupAnimation)>,
 ) {
-    for (player_position, mut player_hotbar) in players.iter_mut() {
-        let chunk_position = ChunkPosition::from(player_position.translation());
-        let item_entities = match model_map.get_entities(&chunk_position) {
-            Some(e) => e,
-            None => continue,
+    for (entity, mut transform, mut animation) in animations.iter_mut() {
+        animation.timer.tick(time.delta());
+
+        let Ok(player_transform) = players.get(animation.player_entity) else {
+            commands.entity(entity).despawn();
+            continue;
         };
 
-        for item_entity in item_entities.iter() {
-            if let Ok((entity, mut dropped_item, transform)) = dropped_items.get_mut(*item_entity) {
-                if transform
-                    .translation
-                    .distance_squared(player_position.translation())
-                    < 2.0
-                {
-                    // First test that the item can be picked up. This is to avoid triggering
-                    // change detection for the hotbar. If detection is triggered, it will send
-                    // an interface update to the client. Can't pick up = spam
-                    let mut capacity = false;
-                    for item_stack in player_hotbar.iter() {
-                        if (item_stack.item() == dropped_item.item()
-                            && item_stack.remaining_capacity() != 0)
-                            || item_stack.is_empty()
-                        {
-                            capacity = true;
-                            break;
-                        }
-                    }
-                    if !capacity {
-                        break;
-                    }
-
-                    // First try to fill item stacks that already have the item
-                    for item_stack in player_hotbar.iter_mut() {
-                        if item_stack.item() == dropped_item.item() {
-                            dropped_item.transfer_to(item_stack, u32::MAX);
-                        }
-
-                        if dropped_item.is_empty() {
-                            break;
-                        }
-                    }
-
-                    if dropped_item.is_empty() {
-                        commands.entity(entity).despawn();
-                        continue;
-                    }
-
-                    // Then go again and fill empty spots
-                    for item_stack in player_hotbar.iter_mut() {
-                        if item_stack.is_empty() {
-                            dropped_item.transfer_to(item_stack, u32::MAX);
-                        }
-
-                        if dropped_item.is_empty() {
-                            break;
-                        }
-                    }
-
-                    if dropped_item.is_empty() {
-                        commands.entity(entity).despawn();
-                        continue;
-                    }
+        if animation.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let target = player_transform.translation() + DVec3::Y * 0.5;
+        transform.translation = animation
+            .start
+            .lerp(target, animation.timer.fraction() as f64);
+    }
+}
+
+fn despawn_old_items(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut dropped_items: Query<(Entity, &mut DroppedItemAge)>,
+) {
+    let lifetime = Duration::from_secs(settings.dropped_item_lifetime);
+
+    for (entity, mut age) in dropped_items.iter_mut() {
+        age.0 += time.delta();
+        if age.0 > lifetime {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+type DroppedItemQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut DroppedItem,
+        &'static Transform,
+        &'static mut DroppedItemAge,
+    ),
+>;
+
+// Keeps the amount of dropped items down by merging items of the same kind. Chunks that still
+// have too many items after merging lose their oldest items.
+fn merge_items(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut dropped_items: DroppedItemQuery,
+    mut diagnostics: Diagnostics,
+) {
+    let mut chunks: HashMap<ChunkPosition, Vec<Entity>> = HashMap::new();
+    for (entity, _, transform, _) in dropped_items.iter() {
+        chunks
+            .entry(ChunkPosition::from(transform.translation))
+            .or_default()
+            .push(entity);
+    }
+
+    let mut count = 0;
+
+    for mut entities in chunks.into_values() {
+        merge(
+            &mut commands,
+            &mut dropped_items,
+            &mut entities,
+            Some(MERGE_DISTANCE),
+        );
+
+        if entities.len() > settings.dropped_items_per_chunk {
+            merge(&mut commands, &mut dropped_items, &mut entities, None);
+        }
+
+        if entities.len() > settings.dropped_items_per_chunk {
+            entities
+                .sort_by_key(|entity| std::cmp::Reverse(dropped_items.get(*entity).unwrap().3 .0));
+            let excess = entities.len() - settings.dropped_items_per_chunk;
+            for entity in entities.drain(..excess) {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        count += entities.len();
+    }
+
+    diagnostics.add_measurement(&DROPPED_ITEM_COUNT, || count as f64);
+}
+
+fn log_dropped_item_count(diagnostics: Res<DiagnosticsStore>) {
+    if let Some(count) = diagnostics
+        .get(&DROPPED_ITEM_COUNT)
+        .and_then(|diagnostic| diagnostic.value())
+    {
+        info!("There are {} dropped items in the world", count);
+    }
+}
+
+// Moves items into other stacks of the same item within the distance, removing the entities that
+// are emptied.
+fn merge(
+    commands: &mut Commands,
+    dropped_items: &mut DroppedItemQuery,
+    entities: &mut Vec<Entity>,
+    max_distance: Option<f64>,
+) {
+    let mut i = 0;
+    while i < entities.len() {
+        let mut j = i + 1;
+        while j < entities.len() {
+            let [(_, mut item, transform, mut age), (_, mut other, other_transform, other_age)] =
+                dropped_items.many_mut([entities[i], entities[j]]);
+
+            if item.item() == other.item()
+                && item.remaining_capacity() != 0
+                && max_distance.is_none_or(|distance| {
+                    transform
+                        .translation
+                        .distance_squared(other_transform.translation)
+                        < distance * distance
+                })
+            {
+                other.transfer_to(&mut item, u32::MAX);
+                age.0 = age.0.min(other_age.0);
+
+                if other.is_empty() {
+                    commands.entity(entities[j]).despawn();
+                    entities.swap_remove(j);
+                    continue;
                 }
             }
+
+            j += 1;
         }
+        i += 1;
     }
 }
@@ -2,23 +2,31 @@ use std::collections::HashMap;
 
 use fmc::{items::ItemId, prelude::*};
 
+mod actions;
+mod bucket;
+mod crafting;
 mod dropped_items;
 
-pub use dropped_items::DroppedItem;
+pub use actions::{EatEvent, ItemUseTiming};
+pub use crafting::Recipes;
+pub use dropped_items::{DroppedItem, PickupDelay};
 
 pub struct ItemPlugin;
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ItemRegistry::default())
-            .add_plugins(dropped_items::DroppedItemsPlugin);
+            .add_plugins(dropped_items::DroppedItemsPlugin)
+            .add_plugins(bucket::BucketPlugin)
+            .add_plugins(actions::ItemActionPlugin)
+            .add_plugins(crafting::CraftingPlugin);
     }
 }
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ItemUseSystems;
 
-// TODO: Transfer this over to fmc lib and have the entity be part of the ItemConfig of the item
-// that can be used?
+/// Maps items to the entity that handles their use. Items that declare a "use" action in their
+/// item config are registered automatically, others must register an entity with 'ItemUses'.
 #[derive(Resource, Deref, DerefMut, Default)]
 pub struct ItemRegistry(HashMap<ItemId, Entity>);
 
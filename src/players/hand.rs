@@ -1,9 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use fmc::{
     bevy::math::DVec3,
     blocks::{BlockConfig, BlockFace, BlockId, BlockPosition, Blocks},
-    items::{ItemStack, Items},
+    items::{ItemId, ItemStack, Items, ITEM_CONFIG_PATH},
     models::{Model, ModelMap, ModelVisibility},
     networking::{NetworkMessage, Server},
     physics::{shapes::Aabb, Collider},
@@ -13,23 +13,28 @@ use fmc::{
     utils::Rng,
     world::{chunk::ChunkPosition, BlockUpdate, ChunkSubscriptions, WorldMap},
 };
+use serde::Deserialize;
 
 use crate::{
-    items::{DroppedItem, ItemRegistry, ItemUseSystems, ItemUses},
-    players::Hotbar,
+    items::{DroppedItem, ItemRegistry, ItemUseSystems, ItemUseTiming, ItemUses},
+    players::{GameMode, Hotbar, Modifiers},
+    settings::Settings,
+    world::blocks::{BlockEntities, Fluids, PlayerPlacedBlocks, ServerBlockConfigs},
 };
 
 pub struct HandPlugin;
 impl Plugin for HandPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(MiningEvents::default()).add_systems(
-            Update,
-            (
-                handle_left_clicks,
-                handle_right_clicks.in_set(ItemUseSystems),
-                break_blocks.after(handle_left_clicks),
-            ),
-        );
+        app.insert_resource(MiningEvents::default())
+            .add_systems(Startup, load_action_orders)
+            .add_systems(
+                Update,
+                (
+                    handle_left_clicks,
+                    handle_right_clicks.in_set(ItemUseSystems),
+                    break_blocks.after(handle_left_clicks),
+                ),
+            );
     }
 }
 
@@ -50,12 +55,15 @@ impl HandInteractions {
 }
 
 fn handle_left_clicks(
+    net: Res<Server>,
+    settings: Res<Settings>,
+    world_map: Res<WorldMap>,
     mut clicks: EventReader<NetworkMessage<messages::LeftClick>>,
-    player_query: Query<(&Targets, &Camera, &GlobalTransform), With<Player>>,
+    player_query: Query<(&Player, &Targets, &Camera, &GlobalTransform)>,
     mut block_breaking_events: ResMut<MiningEvents>,
 ) {
     for click in clicks.read() {
-        let (targets, camera, transform) = player_query.get(click.player_entity).unwrap();
+        let (player, targets, camera, transform) = player_query.get(click.player_entity).unwrap();
 
         let camera_position = transform.translation() + camera.translation;
 
@@ -71,6 +79,30 @@ fn handle_left_clicks(
                     let block_config = Blocks::get().get_config(block_id);
 
                     if block_config.hardness.is_some() {
+                        let violation = if !in_reach(camera_position, *block_position) {
+                            Some("out of reach")
+                        } else if !in_line_of_sight(
+                            &world_map,
+                            camera_position,
+                            camera.forward(),
+                            *block_position,
+                        ) {
+                            Some("not in line of sight")
+                        } else {
+                            None
+                        };
+
+                        if let Some(violation) = violation {
+                            warn!(
+                                "Player '{}' tried to mine a block that is {}, at {:?}",
+                                player.username, violation, **block_position
+                            );
+                            if settings.kick_on_mining_violation {
+                                net.disconnect(click.player_entity);
+                            }
+                            break;
+                        }
+
                         let hit_position = camera_position + camera.forward() * *distance;
                         block_breaking_events.insert(
                             *block_position,
@@ -86,6 +118,98 @@ fn handle_left_clicks(
     }
 }
 
+// Minimum time between two blocks broken instantly, either because the player is in creative or
+// because the block has no hardness.
+const INSTANT_BREAK_COOLDOWN: std::time::Duration = std::time::Duration::from_millis(150);
+
+// Test if any part of the block is within reach of the camera.
+fn in_reach(camera_position: DVec3, block_position: BlockPosition) -> bool {
+    let min = block_position.as_dvec3();
+    let closest_point = camera_position.clamp(min, min + DVec3::ONE);
+    camera_position.distance(closest_point) <= REACH_DISTANCE
+}
+
+// Walks the blocks along the ray until it reaches the target, returns false if the ray hits the
+// hitbox of a block in the way first. Partial blocks like slabs and stairs only block the part of
+// the ray that passes through their hitbox.
+fn in_line_of_sight(
+    world_map: &WorldMap,
+    origin: DVec3,
+    direction: DVec3,
+    target: BlockPosition,
+) -> bool {
+    let blocks = Blocks::get();
+
+    let mut position = BlockPosition::from(origin);
+    let step = direction.signum().as_ivec3();
+    // Distance along the ray to cross one block on each axis
+    let delta = direction.recip().abs();
+    let next_boundary = position.as_dvec3() + step.max(IVec3::ZERO).as_dvec3();
+    let mut next_crossing = ((next_boundary - origin) / direction).abs();
+    // Components that are zero never cross a boundary
+    for i in 0..3 {
+        if direction[i] == 0.0 {
+            next_crossing[i] = f64::INFINITY;
+        }
+    }
+
+    loop {
+        if position == target {
+            return true;
+        }
+
+        // The camera may be inside a partial block, so the block it is in doesn't count.
+        if BlockPosition::from(origin) != position {
+            let hitbox = world_map.get_block(position).and_then(|block_id| {
+                blocks
+                    .get_config(&block_id)
+                    .hitbox(world_map.get_block_state(position))
+            });
+            if hitbox.is_some_and(|hitbox| {
+                ray_hits_collider(&hitbox, position.as_dvec3(), origin, direction)
+            }) {
+                return false;
+            }
+        }
+
+        let axis = if next_crossing.x < next_crossing.y && next_crossing.x < next_crossing.z {
+            0
+        } else if next_crossing.y < next_crossing.z {
+            1
+        } else {
+            2
+        };
+
+        // The ray may enter the target further away than its closest point, but never by more
+        // than the diagonal of a block.
+        if next_crossing[axis] > REACH_DISTANCE + 3f64.sqrt() {
+            return false;
+        }
+
+        position[axis] += step[axis];
+        next_crossing[axis] += delta[axis];
+    }
+}
+
+// Test if the ray intersects the collider of a block, the collider is relative to the block's
+// corner at 'offset'.
+fn ray_hits_collider(collider: &Collider, offset: DVec3, origin: DVec3, direction: DVec3) -> bool {
+    let aabbs = match collider {
+        Collider::Aabb(aabb) => std::slice::from_ref(aabb),
+        Collider::Compound(aabbs) => aabbs.as_slice(),
+    };
+
+    aabbs.iter().any(|aabb| {
+        let min = (offset + aabb.min() - origin) / direction;
+        let max = (offset + aabb.max() - origin) / direction;
+        // Zero components of the direction give infinities, which fall out of the min/max as
+        // long as the origin is within the slab of that axis, and NaN, which is ignored.
+        let enter = min.min(max).max_element();
+        let exit = min.max(max).min_element();
+        enter <= exit && exit >= 0.0
+    })
+}
+
 #[derive(Resource, Deref, DerefMut, Default, Debug)]
 struct MiningEvents(HashMap<BlockPosition, (Entity, BlockId, BlockFace, DVec3)>);
 
@@ -94,27 +218,35 @@ struct MiningEvents(HashMap<BlockPosition, (Entity, BlockId, BlockFace, DVec3)>)
 struct BreakingBlock {
     model_entity: Entity,
     progress: f32,
-    prev_hit: std::time::Instant,
+    prev_hit: std::time::Duration,
     particle_timer: Timer,
 }
 
 #[derive(Component)]
 struct BreakingBlockMarker;
 
+// Mining done by a player, regardless of which block
+struct MiningPlayer {
+    prev_hit: std::time::Duration,
+    prev_break: Option<std::time::Duration>,
+}
+
 fn break_blocks(
     mut commands: Commands,
     time: Res<Time>,
     net: Res<Server>,
     items: Res<Items>,
     chunk_subscriptions: Res<ChunkSubscriptions>,
-    hotbar_query: Query<&Hotbar, With<Player>>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    mut hotbar_query: Query<(&mut Hotbar, &GameMode), With<Player>>,
     mut model_query: Query<(&mut Model, &mut ModelVisibility), With<BreakingBlockMarker>>,
     mut block_update_writer: EventWriter<BlockUpdate>,
     mut mining_events: ResMut<MiningEvents>,
     mut being_broken: Local<HashMap<BlockPosition, BreakingBlock>>,
+    mut mining_players: Local<HashMap<Entity, MiningPlayer>>,
     mut rng: Local<Rng>,
 ) {
-    let now = std::time::Instant::now();
+    let now = time.elapsed();
 
     let blocks = Blocks::get();
 
@@ -128,7 +260,7 @@ fn break_blocks(
             continue;
         };
 
-        let hotbar = hotbar_query.get(player_entity).unwrap();
+        let (mut hotbar, game_mode) = hotbar_query.get_mut(player_entity).unwrap();
 
         let tool_config = if let Some(item) = hotbar.held_item_stack().item() {
             Some(items.get_config(&item.id))
@@ -136,7 +268,23 @@ fn break_blocks(
             None
         };
 
-        let broken = if let Some(breaking_block) = being_broken.get_mut(&block_position) {
+        let mining_player = mining_players.entry(player_entity).or_insert(MiningPlayer {
+            prev_hit: now,
+            prev_break: None,
+        });
+        let player_prev_hit = std::mem::replace(&mut mining_player.prev_hit, now);
+
+        let broken = if *game_mode == GameMode::Creative || hardness == 0.0 {
+            // The cooldown keeps players from clearing everything in sight while holding the
+            // button down.
+            if mining_player
+                .prev_break
+                .is_some_and(|prev_break| now - prev_break < INSTANT_BREAK_COOLDOWN)
+            {
+                continue;
+            }
+            true
+        } else if let Some(breaking_block) = being_broken.get_mut(&block_position) {
             if (now - breaking_block.prev_hit).as_secs_f32() > 0.05 {
                 // The interval between two clicks needs to be short in order to be counted as
                 // holding the button down.
@@ -181,8 +329,10 @@ fn break_blocks(
             } else {
                 1.0
             };
-            breaking_block.progress +=
-                (now - breaking_block.prev_hit).as_secs_f32() / hardness * efficiency;
+            // Time is only counted once for each player, so hitting several blocks in turn does
+            // not break them any faster than one at a time.
+            let elapsed = (now - breaking_block.prev_hit).min(now - player_prev_hit);
+            breaking_block.progress += elapsed.as_secs_f32() / hardness * efficiency;
             breaking_block.prev_hit = now;
 
             let Model::Custom {
@@ -225,8 +375,9 @@ fn break_blocks(
             false
         };
 
-        // When hardness is zero it will break instantly
-        if broken || hardness == 0.0 {
+        if broken {
+            mining_player.prev_break = Some(now);
+
             let chunk_position = ChunkPosition::from(block_position);
             if let Some(subscribers) = chunk_subscriptions.get_subscribers(&chunk_position) {
                 let position = block_position.as_dvec3() + DVec3::splat(0.5);
@@ -261,9 +412,35 @@ fn break_blocks(
                 block_data: None,
             });
 
+            // Tools are not worn and nothing is dropped in creative
+            if *game_mode == GameMode::Creative {
+                continue;
+            }
+
+            let tool_tier = hotbar
+                .held_item_stack()
+                .item()
+                .and_then(|item| item.properties["tier"].as_u64())
+                .unwrap_or(0) as u32;
+            let has_tool_tier = tool_tier >= server_block_configs.get(&block_id).tool_tier;
+
+            if wear_tool(hotbar.held_item_stack_mut()) {
+                if let Some(subscribers) = chunk_subscriptions.get_subscribers(&chunk_position) {
+                    net.send_many(
+                        subscribers,
+                        messages::Sound {
+                            position: Some(hit_position),
+                            volume: 1.0,
+                            speed: 1.0,
+                            sound: "tool_break.ogg".to_owned(),
+                        },
+                    );
+                }
+            }
+
             let (dropped_item_id, count) = match block_config.drop(tool_config) {
-                Some(drop) => drop,
-                None => continue,
+                Some(drop) if has_tool_tier => drop,
+                _ => continue,
             };
 
             let item_config = items.get_config(&dropped_item_id);
@@ -296,7 +473,7 @@ fn break_blocks(
                 BreakingBlock {
                     model_entity,
                     progress: 0.0,
-                    prev_hit: std::time::Instant::now(),
+                    prev_hit: now,
                     particle_timer,
                 },
             );
@@ -315,6 +492,27 @@ fn break_blocks(
             return true;
         }
     });
+
+    mining_players.retain(|_, mining_player| (now - mining_player.prev_hit).as_secs_f32() < 1.0);
+}
+
+// Lowers the durability of the item stack if it is a tool. Returns true if the tool broke.
+fn wear_tool(item_stack: &mut ItemStack) -> bool {
+    let Some(item) = item_stack.item_mut() else {
+        return false;
+    };
+
+    let Some(durability) = item.properties["durability"].as_u64() else {
+        return false;
+    };
+
+    if durability > 1 {
+        item.properties["durability"] = (durability - 1).into();
+        false
+    } else {
+        item_stack.take(1);
+        true
+    }
 }
 
 fn hit_particles(
@@ -476,106 +674,231 @@ fn build_breaking_model() -> Model {
     }
 }
 
+/// What a right click can do, they are tried in order until one of them succeeds.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Interact,
+    PlaceBlock,
+    UseItem,
+}
+
+const DEFAULT_ACTION_ORDER: [Action; 3] = [Action::Interact, Action::PlaceBlock, Action::UseItem];
+
+// How far the closest point of a block can be from the camera for players to mine it. Creative
+// players also place blocks this far out when they are not looking at one.
+const REACH_DISTANCE: f64 = 5.0;
+
+/// Items can change what their right click does first through the "action_order" property of
+/// their item config, e.g. "action_order": ["use_item", "interact"]. Actions that are left out
+/// are never done.
+#[derive(Resource, Deref, Default)]
+struct ActionOrders(HashMap<ItemId, Vec<Action>>);
+
+fn load_action_orders(mut commands: Commands, items: Res<Items>) {
+    let mut action_orders = ActionOrders::default();
+
+    let directory = match std::fs::read_dir(ITEM_CONFIG_PATH) {
+        Ok(dir) => dir,
+        Err(e) => panic!(
+            "Failed to read the item config directory at '{}'\nError: {}",
+            ITEM_CONFIG_PATH, e
+        ),
+    };
+
+    for dir_entry in directory {
+        let file_path = dir_entry.unwrap().path();
+        let Some(item_id) = file_path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .and_then(|name| items.get_id(name))
+        else {
+            continue;
+        };
+
+        let item_config = items.get_config(&item_id);
+        let Some(json) = item_config.properties.get("action_order") else {
+            continue;
+        };
+
+        let order: Vec<Action> = match serde_json::from_value(json.clone()) {
+            Ok(order) => order,
+            Err(e) => panic!(
+                "Startup failed while reading the action order of the item '{}' at '{}'\nError: {}",
+                item_config.name,
+                file_path.display(),
+                e
+            ),
+        };
+        action_orders.0.insert(item_id, order);
+    }
+
+    commands.insert_resource(action_orders);
+}
+
 fn handle_right_clicks(
     net: Res<Server>,
     world_map: Res<WorldMap>,
     items: Res<Items>,
-    item_registry: Res<ItemRegistry>,
-    model_map: Res<ModelMap>,
     chunk_subscriptions: Res<ChunkSubscriptions>,
-    model_query: Query<(&Collider, &GlobalTransform), (With<Model>, Without<BlockPosition>)>,
-    mut player_query: Query<(&mut Hotbar, &Targets, &Camera), With<Player>>,
-    mut item_use_query: Query<&mut ItemUses>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    block_entities: Res<BlockEntities>,
+    mut player_placed_blocks: ResMut<PlayerPlacedBlocks>,
+    // Everything that can be in the way of a placed block
+    (model_map, model_query, player_colliders): (
+        Res<ModelMap>,
+        Query<(&Collider, &GlobalTransform), (With<Model>, Without<BlockPosition>)>,
+        Query<(&Collider, &GlobalTransform), With<Player>>,
+    ),
+    // Grouped as systems can only take 16 parameters
+    (item_registry, action_orders): (Res<ItemRegistry>, Res<ActionOrders>),
+    (fluids, settings): (Res<Fluids>, Res<Settings>),
+    mut player_query: Query<
+        (
+            &mut Hotbar,
+            &Targets,
+            &Camera,
+            &GlobalTransform,
+            &Modifiers,
+            &GameMode,
+        ),
+        With<Player>,
+    >,
+    mut item_use_query: Query<(&mut ItemUses, Option<&mut ItemUseTiming>)>,
     mut hand_interaction_query: Query<&mut HandInteractions>,
     mut block_update_writer: EventWriter<BlockUpdate>,
     mut clicks: EventReader<NetworkMessage<messages::RightClick>>,
     mut rng: Local<Rng>,
 ) {
-    // TODO: ActionOrder currently does nothing, but there needs to be some system for deviating
-    // from the set order. Like if you hold shift, placing blocks should take precedence over
-    // interacting. And there's a bunch of stuff like this where you want to do something else
-    // depending on some condition.
-    enum ActionOrder {
-        Interact,
-        PlaceBlock,
-        UseItem,
-    }
-
     for right_click in clicks.read() {
-        let (mut hotbar, targets, camera) =
+        let (mut hotbar, targets, camera, transform, modifiers, game_mode) =
             player_query.get_mut(right_click.player_entity).unwrap();
 
-        let mut action = ActionOrder::Interact;
+        let action_order = hotbar
+            .held_item_stack()
+            .item()
+            .and_then(|item| action_orders.get(&item.id))
+            .map(Vec::as_slice)
+            .unwrap_or(&DEFAULT_ACTION_ORDER);
 
-        'outer: loop {
+        'actions: for action in action_order {
             match action {
-                ActionOrder::Interact => {
+                // Sneaking lets players place blocks against things they could interact with.
+                Action::Interact if modifiers.sneak => continue,
+                Action::Interact => {
                     for target in targets.iter() {
-                        let Some(entity) = target.entity() else {
+                        let entity = match target {
+                            Target::Block {
+                                block_position,
+                                entity: None,
+                                ..
+                            } => block_entities.get(block_position),
+                            _ => target.entity(),
+                        };
+                        let Some(entity) = entity else {
                             continue;
                         };
 
                         if let Ok(mut interactions) = hand_interaction_query.get_mut(entity) {
                             interactions.push(right_click.player_entity);
-                            break 'outer;
+                            break 'actions;
                         }
                     }
-
-                    action = ActionOrder::PlaceBlock;
                 }
-                ActionOrder::PlaceBlock => {
+                Action::PlaceBlock => {
                     let blocks = Blocks::get();
+                    let camera_position = transform.translation() + camera.translation;
+
+                    // Creative players can build against anything, survival players only against
+                    // solid blocks and the surface of fluids. Fluids are only built against when
+                    // there is no solid block in reach, so that players can build underwater.
+                    let target = match game_mode {
+                        GameMode::Creative => targets.get_first_block(|_| true),
+                        GameMode::Survival => targets
+                            .get_first_block(|block_id| {
+                                blocks.get_config(block_id).hardness.is_some()
+                            })
+                            .or_else(|| {
+                                targets.get_first_block(|block_id| fluids.is_fluid(*block_id))
+                            }),
+                    };
 
-                    let Some(Target::Block {
-                        block_position,
-                        block_id,
-                        block_face,
-                        ..
-                    }) = targets
-                        .get_first_block(|block_id| blocks.get_config(block_id).hardness.is_some())
-                    else {
-                        action = ActionOrder::UseItem;
-                        continue;
+                    let (block_position, block_id, block_face, distance) = match target {
+                        Some(Target::Block {
+                            block_position,
+                            block_id,
+                            block_face,
+                            distance,
+                            ..
+                        }) => (*block_position, *block_id, *block_face, *distance),
+                        // With nothing to place against, the block is placed in the air as far
+                        // away as the player can reach.
+                        _ if *game_mode == GameMode::Creative
+                            && settings.creative_air_placement =>
+                        {
+                            let block_position = BlockPosition::from(
+                                camera_position + camera.forward() * REACH_DISTANCE,
+                            );
+                            let Some(block_id) = world_map.get_block(block_position) else {
+                                continue;
+                            };
+                            (block_position, block_id, BlockFace::Top, REACH_DISTANCE)
+                        }
+                        _ => continue,
                     };
 
-                    let blocks = Blocks::get();
                     let equipped_item_stack = hotbar.held_item_stack_mut();
 
                     if let Some((block_id, replaced_block_position)) = block_placement(
                         &equipped_item_stack,
-                        *block_id,
-                        *block_face,
-                        *block_position,
+                        block_id,
+                        block_face,
+                        block_position,
+                        *game_mode,
                         &items,
                         &blocks,
+                        &fluids,
                         &world_map,
                     ) {
+                        let hit_position = camera_position + camera.forward() * distance;
+
+                        // Blocks like slabs are placed upside down when placed against the
+                        // underside of a block, or the upper half of its sides.
+                        let upper_half = match block_face {
+                            BlockFace::Top => false,
+                            BlockFace::Bottom => true,
+                            _ => hit_position.y - block_position.y as f64 > 0.5,
+                        };
+                        let block_id = match &server_block_configs.get(&block_id).upper_half {
+                            Some(block_name) if upper_half => blocks.get_id(block_name),
+                            _ => block_id,
+                        };
+
                         let block_config = blocks.get_config(&block_id);
-                        let block_state = block_config.placement_rotation(*block_face, &camera);
+                        let block_state = block_config.placement_rotation(block_face, &camera);
 
                         let replaced_collider = Collider::Aabb(Aabb {
-                            center: replaced_block_position.as_dvec3(),
+                            center: replaced_block_position.as_dvec3() + DVec3::splat(0.5),
                             half_extents: DVec3::splat(0.5),
                         });
 
-                        // Check that there aren't any entities in the way of the new block
-                        let chunk_position = ChunkPosition::from(replaced_block_position);
-                        if let Some(entities) = model_map.get_entities(&chunk_position) {
-                            for (collider, global_transform) in model_query.iter_many(entities) {
-                                if collider
-                                    .intersection(
-                                        &global_transform.compute_transform(),
-                                        &replaced_collider,
-                                        &Transform::IDENTITY,
-                                    )
-                                    .is_some()
-                                {
-                                    continue;
-                                }
-                            }
+                        if is_obstructed(
+                            &replaced_collider,
+                            &model_map,
+                            &model_query,
+                            &player_colliders,
+                        ) {
+                            // Send the hotbar again in case the client assumed the item was used.
+                            hotbar.set_changed();
+                            break;
                         }
 
-                        equipped_item_stack.take(1);
+                        let chunk_position = ChunkPosition::from(replaced_block_position);
+
+                        // Creative players have an endless supply
+                        if *game_mode != GameMode::Creative {
+                            equipped_item_stack.take(1);
+                        }
 
                         if let Some(subscribers) =
                             chunk_subscriptions.get_subscribers(&chunk_position)
@@ -595,6 +918,11 @@ fn handle_right_clicks(
                             }
                         }
 
+                        // Only blocks that decay need to know, placed leaves should stay.
+                        if server_block_configs.get(&block_id).decay_distance.is_some() {
+                            player_placed_blocks.insert(replaced_block_position);
+                        }
+
                         block_update_writer.send(BlockUpdate::Replace {
                             position: replaced_block_position,
                             block_id,
@@ -603,42 +931,85 @@ fn handle_right_clicks(
                         });
 
                         break;
-                    } else {
-                        action = ActionOrder::UseItem;
                     }
                 }
-                ActionOrder::UseItem => {
-                    // If nothing else was done, we try to use the item
+                Action::UseItem => {
                     let equipped_item_stack = hotbar.held_item_stack_mut();
 
                     let Some(item) = equipped_item_stack.item() else {
-                        break;
+                        continue;
                     };
 
                     if let Some(item_use_entity) = item_registry.get(&item.id) {
-                        let mut uses = item_use_query.get_mut(*item_use_entity).unwrap();
-                        uses.push(right_click.player_entity);
+                        let (mut uses, timing) = item_use_query.get_mut(*item_use_entity).unwrap();
+                        if timing.is_none_or(|mut timing| timing.try_use(right_click.player_entity))
+                        {
+                            uses.push(right_click.player_entity);
+                        }
+                        break;
                     }
-
-                    break;
                 }
             }
         }
     }
 }
 
+// Test if the collider intersects any player or model.
+fn is_obstructed(
+    collider: &Collider,
+    model_map: &ModelMap,
+    model_query: &Query<(&Collider, &GlobalTransform), (With<Model>, Without<BlockPosition>)>,
+    player_colliders: &Query<(&Collider, &GlobalTransform), With<Player>>,
+) -> bool {
+    let intersects = |(other, global_transform): (&Collider, &GlobalTransform)| {
+        other
+            .intersection(
+                &global_transform.compute_transform(),
+                collider,
+                &Transform::IDENTITY,
+            )
+            .is_some()
+    };
+
+    if player_colliders.iter().any(intersects) {
+        return true;
+    }
+
+    // Models are stored by the chunk they are in, but they may stick out into the neighbouring
+    // chunks, so all chunks within a block of the collider are checked.
+    let aabb = collider.as_aabb();
+    let min = aabb.min() - DVec3::ONE;
+    let max = aabb.max() + DVec3::ONE;
+    let mut chunk_positions = HashSet::new();
+    for x in [min.x, max.x] {
+        for y in [min.y, max.y] {
+            for z in [min.z, max.z] {
+                let block_position = BlockPosition::from(DVec3::new(x, y, z));
+                chunk_positions.insert(ChunkPosition::from(block_position));
+            }
+        }
+    }
+
+    chunk_positions
+        .iter()
+        .filter_map(|chunk_position| model_map.get_entities(chunk_position))
+        .any(|entities| model_query.iter_many(entities).any(intersects))
+}
+
 fn block_placement(
     equipped_item_stack: &ItemStack,
     block_id: BlockId,
     block_face: BlockFace,
     block_position: BlockPosition,
+    game_mode: GameMode,
     items: &Items,
     blocks: &Blocks,
+    fluids: &Fluids,
     world_map: &WorldMap,
 ) -> Option<(BlockId, BlockPosition)> {
     let against_block = blocks.get_config(&block_id);
 
-    if !against_block.is_solid() {
+    if game_mode == GameMode::Survival && !against_block.is_solid() && !fluids.is_fluid(block_id) {
         return None;
     }
 
@@ -659,7 +1030,7 @@ fn block_placement(
     }
 
     let replaced_block_position = if against_block.replaceable {
-        // Some blocks, like grass, can be replaced instead of placing the new
+        // Some blocks, like grass and fluids, can be replaced instead of placing the new
         // block adjacently to it.
         block_position
     } else if let Some(block_id) = world_map.get_block(block_face.shift_position(block_position)) {
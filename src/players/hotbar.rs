@@ -1,18 +1,28 @@
 use fmc::{
+    bevy::math::DVec3,
     networking::{NetworkMessage, Server},
-    players::Player,
+    physics::Physics,
+    players::{Camera, Player},
     prelude::*,
     protocol::messages,
 };
 
-use crate::players::Hotbar;
+use crate::{
+    items::{DroppedItem, PickupDelay},
+    players::{item_box_update, Hotbar},
+};
 
 pub struct HotbarPlugin;
 impl Plugin for HotbarPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            (initialize_interface, send_server_updates, equip_item),
+            (
+                initialize_interface,
+                send_server_updates,
+                equip_item,
+                drop_items,
+            ),
         );
     }
 }
@@ -31,23 +41,7 @@ fn initialize_interface(net: Res<Server>, new_player_query: Query<Entity, Added<
 
 fn send_server_updates(net: Res<Server>, hotbar_query: Query<(Entity, &Hotbar), Changed<Hotbar>>) {
     for (player_entity, hotbar) in hotbar_query.iter() {
-        let mut hotbar_node = messages::InterfaceItemBoxUpdate::default();
-        for (i, item_stack) in hotbar.iter().enumerate() {
-            if let Some(item) = item_stack.item() {
-                hotbar_node.add_itembox(
-                    "hotbar",
-                    i as u32,
-                    item.id,
-                    item_stack.size(),
-                    item.properties["durability"].as_u64().map(|v| v as u32),
-                    item.properties["description"].as_str(),
-                );
-            } else {
-                hotbar_node.add_empty_itembox("hotbar", i as u32);
-            }
-        }
-
-        net.send_one(player_entity, hotbar_node);
+        net.send_one(player_entity, item_box_update("hotbar", hotbar));
     }
 }
 fn equip_item(
@@ -69,3 +63,47 @@ fn equip_item(
         hotbar.equipped_item = equip_event.index as usize;
     }
 }
+
+// Throws the equipped item in the direction the player is looking, '/drop' throws one item and
+// '/drop stack' the whole stack.
+fn drop_items(
+    mut commands: Commands,
+    mut hotbar_query: Query<(&mut Hotbar, &Camera, &GlobalTransform), With<Player>>,
+    mut chat_messages: EventReader<NetworkMessage<messages::InterfaceTextInput>>,
+) {
+    for chat_message in chat_messages.read() {
+        if &chat_message.interface_path != "chat/input" {
+            continue;
+        }
+
+        let quantity = match chat_message.text.as_str() {
+            "/drop" => 1,
+            "/drop stack" => u32::MAX,
+            _ => continue,
+        };
+
+        let Ok((mut hotbar, camera, transform)) = hotbar_query.get_mut(chat_message.player_entity)
+        else {
+            continue;
+        };
+
+        if hotbar.held_item_stack().is_empty() {
+            continue;
+        }
+
+        let held_item_stack = hotbar.held_item_stack_mut();
+        let item_stack = held_item_stack.take(quantity.min(held_item_stack.size()));
+
+        let camera_position = transform.translation() + camera.translation;
+        commands.spawn((
+            DroppedItem::new(item_stack),
+            PickupDelay::new(chat_message.player_entity),
+            Transform::from_translation(camera_position - DVec3::Y * 0.3),
+            Physics {
+                enabled: true,
+                velocity: camera.forward() * 6.0 + DVec3::Y * 2.0,
+                ..default()
+            },
+        ));
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, VecDeque};
+
+use fmc::{
+    bevy::math::DVec3,
+    items::{ItemStack, Items},
+    networking::{NetworkMessage, Server},
+    players::Player,
+    prelude::*,
+    protocol::messages,
+};
+
+use crate::{
+    items::{DroppedItem, Recipes},
+    players::{
+        insert_item_stack, item_box_update, CraftingGrid, HeldInterfaceStack, Hotbar, Inventory,
+    },
+};
+
+pub struct InventoryPlugin;
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (move_items, close_inventory, send_inventory_updates).chain(),
+        );
+    }
+}
+
+/// A take or place of items the client has done in one of its interfaces.
+pub struct ItemMove {
+    pub interface_path: String,
+    pub index: usize,
+    pub quantity: u32,
+    pub is_take: bool,
+}
+
+impl ItemMove {
+    /// Move the items between the item stack and the held stack, returns false if it isn't
+    /// possible.
+    pub fn apply(&self, item_stack: &mut ItemStack, held_stack: &mut ItemStack) -> bool {
+        if self.is_take {
+            take_item(item_stack, held_stack, self.quantity)
+        } else {
+            place_item(held_stack, item_stack, self.quantity)
+        }
+    }
+}
+
+/// The item moves of a player, see 'read_item_moves'
+#[derive(Default)]
+pub struct ItemMoves {
+    takes: VecDeque<ItemMove>,
+    places: VecDeque<ItemMove>,
+}
+
+impl ItemMoves {
+    /// Takes and places arrive as separate events, so the order between them is lost. A take is
+    /// only possible with nothing held, and a place only when something is, which is enough to
+    /// restore it.
+    pub fn next(&mut self, held_stack: &ItemStack) -> Option<ItemMove> {
+        if held_stack.is_empty() {
+            self.takes.pop_front().or_else(|| self.places.pop_front())
+        } else {
+            self.places.pop_front().or_else(|| self.takes.pop_front())
+        }
+    }
+}
+
+/// Collect the item moves of each player in the interfaces whose path starts with the prefix.
+///
+/// The client moves the items locally and tells the server. Shift clicking and dragging are sent
+/// as a series of takes and places, they should be applied one at a time with 'ItemMoves::next'.
+/// Moves that aren't possible should resynchronize the client.
+pub fn read_item_moves(
+    take_events: &mut EventReader<NetworkMessage<messages::InterfaceTakeItem>>,
+    place_events: &mut EventReader<NetworkMessage<messages::InterfacePlaceItem>>,
+    interface_prefix: &str,
+) -> HashMap<Entity, ItemMoves> {
+    let mut item_moves: HashMap<Entity, ItemMoves> = HashMap::new();
+
+    for take in take_events.read() {
+        if take.interface_path.starts_with(interface_prefix) {
+            item_moves
+                .entry(take.player_entity)
+                .or_default()
+                .takes
+                .push_back(ItemMove {
+                    interface_path: take.interface_path.clone(),
+                    index: take.index as usize,
+                    quantity: take.quantity,
+                    is_take: true,
+                });
+        }
+    }
+
+    for place in place_events.read() {
+        if place.interface_path.starts_with(interface_prefix) {
+            item_moves
+                .entry(place.player_entity)
+                .or_default()
+                .places
+                .push_back(ItemMove {
+                    interface_path: place.interface_path.clone(),
+                    index: place.index as usize,
+                    quantity: place.quantity,
+                    is_take: false,
+                });
+        }
+    }
+
+    item_moves
+}
+
+// Picks up part of the item stack, only possible if nothing is held.
+fn take_item(item_stack: &mut ItemStack, held_stack: &mut ItemStack, quantity: u32) -> bool {
+    if !held_stack.is_empty() || quantity == 0 || quantity > item_stack.size() {
+        return false;
+    }
+
+    *held_stack = item_stack.take(quantity);
+    true
+}
+
+// Places part of the held item stack, if the item stack is of another item the whole held stack
+// must be placed and they swap places.
+fn place_item(held_stack: &mut ItemStack, item_stack: &mut ItemStack, quantity: u32) -> bool {
+    if held_stack.is_empty() || quantity == 0 || quantity > held_stack.size() {
+        return false;
+    }
+
+    if item_stack.is_empty() {
+        held_stack.transfer_to(item_stack, quantity);
+    } else if item_stack.item() == held_stack.item() {
+        if item_stack.remaining_capacity() < quantity {
+            return false;
+        }
+        held_stack.transfer_to(item_stack, quantity);
+    } else if quantity == held_stack.size() {
+        std::mem::swap(held_stack, item_stack);
+    } else {
+        return false;
+    }
+
+    true
+}
+
+fn move_items(
+    net: Res<Server>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    mut players: Query<
+        (
+            &mut Inventory,
+            &mut Hotbar,
+            &mut CraftingGrid,
+            &mut HeldInterfaceStack,
+        ),
+        With<Player>,
+    >,
+    mut take_events: EventReader<NetworkMessage<messages::InterfaceTakeItem>>,
+    mut place_events: EventReader<NetworkMessage<messages::InterfacePlaceItem>>,
+) {
+    let item_moves = read_item_moves(&mut take_events, &mut place_events, "inventory/");
+
+    for (player_entity, mut item_moves) in item_moves {
+        let Ok((mut inventory, mut hotbar, mut crafting_grid, mut held_stack)) =
+            players.get_mut(player_entity)
+        else {
+            continue;
+        };
+
+        while let Some(item_move) = item_moves.next(&held_stack) {
+            if item_move.interface_path == "inventory/crafting_result" {
+                // The whole output is taken at once, and it can only be placed elsewhere.
+                if !item_move.is_take
+                    || !craft(&items, &recipes, &mut crafting_grid, &mut held_stack)
+                {
+                    crafting_grid.set_changed();
+                }
+                continue;
+            }
+
+            let item_stack = match item_move.interface_path.as_str() {
+                "inventory/storage" => inventory.get_mut(item_move.index),
+                "inventory/hotbar" => hotbar.get_mut(item_move.index),
+                "inventory/crafting" => crafting_grid.get_mut(item_move.index),
+                _ => None,
+            };
+
+            let Some(item_stack) = item_stack else {
+                net.disconnect(player_entity);
+                break;
+            };
+
+            if !item_move.apply(item_stack, &mut held_stack) {
+                // Triggers change detection so the interfaces are sent again.
+                inventory.set_changed();
+                hotbar.set_changed();
+                crafting_grid.set_changed();
+            }
+        }
+    }
+}
+
+// Craft once and add the output to the held stack.
+fn craft(
+    items: &Items,
+    recipes: &Recipes,
+    crafting_grid: &mut CraftingGrid,
+    held_stack: &mut ItemStack,
+) -> bool {
+    let output = recipes.get_output(items, crafting_grid, CraftingGrid::WIDTH);
+    if output.is_empty() {
+        return false;
+    }
+
+    if !held_stack.is_empty()
+        && (held_stack.item() != output.item() || held_stack.remaining_capacity() < output.size())
+    {
+        return false;
+    }
+
+    let Some(mut output) = recipes.craft(items, crafting_grid, CraftingGrid::WIDTH) else {
+        return false;
+    };
+
+    if held_stack.is_empty() {
+        *held_stack = output;
+    } else {
+        output.transfer_to(held_stack, u32::MAX);
+    }
+
+    true
+}
+
+fn close_inventory(
+    mut commands: Commands,
+    mut players: Query<
+        (
+            &mut Hotbar,
+            &mut Inventory,
+            &mut HeldInterfaceStack,
+            &mut CraftingGrid,
+            &GlobalTransform,
+        ),
+        With<Player>,
+    >,
+    mut close_events: EventReader<NetworkMessage<messages::InterfaceClose>>,
+) {
+    for close_event in close_events.read() {
+        if close_event.interface_path != "inventory" {
+            continue;
+        }
+
+        let Ok((mut hotbar, mut inventory, mut held_stack, mut crafting_grid, transform)) =
+            players.get_mut(close_event.player_entity)
+        else {
+            continue;
+        };
+
+        return_held_stack(
+            &mut commands,
+            &mut hotbar,
+            &mut inventory,
+            &mut held_stack,
+            transform.translation(),
+        );
+
+        // Items left in the crafting grid are returned the same way.
+        for item_stack in crafting_grid.iter_mut() {
+            return_held_stack(
+                &mut commands,
+                &mut hotbar,
+                &mut inventory,
+                item_stack,
+                transform.translation(),
+            );
+        }
+    }
+}
+
+/// Put whatever the player is holding in an interface back in the hotbar or inventory, it is
+/// dropped at the position if there is no room.
+pub fn return_held_stack(
+    commands: &mut Commands,
+    hotbar: &mut Hotbar,
+    inventory: &mut Inventory,
+    held_stack: &mut ItemStack,
+    position: DVec3,
+) {
+    if held_stack.is_empty() {
+        return;
+    }
+
+    insert_item_stack(hotbar, inventory, held_stack);
+
+    if !held_stack.is_empty() {
+        commands.spawn((
+            DroppedItem::new(std::mem::take(held_stack)),
+            Transform::from_translation(position),
+        ));
+    }
+}
+
+fn send_inventory_updates(
+    net: Res<Server>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    players: Query<(Entity, Ref<Inventory>, Ref<Hotbar>, Ref<CraftingGrid>), With<Player>>,
+) {
+    for (player_entity, inventory, hotbar, crafting_grid) in players.iter() {
+        if inventory.is_changed() {
+            net.send_one(
+                player_entity,
+                item_box_update("inventory/storage", &inventory),
+            );
+        }
+
+        if hotbar.is_changed() {
+            net.send_one(player_entity, item_box_update("inventory/hotbar", &hotbar));
+        }
+
+        if crafting_grid.is_changed() {
+            let output = recipes.get_output(&items, &crafting_grid, CraftingGrid::WIDTH);
+            net.send_one(
+                player_entity,
+                item_box_update("inventory/crafting", &crafting_grid),
+            );
+            net.send_one(
+                player_entity,
+                item_box_update("inventory/crafting_result", &[output]),
+            );
+        }
+    }
+}
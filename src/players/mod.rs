@@ -22,8 +22,10 @@ use crate::world::WorldProperties;
 
 mod hand;
 mod hotbar;
+mod inventory;
 
 pub use hand::HandInteractions;
+pub use inventory::{read_item_moves, return_held_stack};
 
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
@@ -31,6 +33,7 @@ impl Plugin for PlayerPlugin {
         app.add_event::<RespawnEvent>()
             .add_plugins(hand::HandPlugin)
             .add_plugins(hotbar::HotbarPlugin)
+            .add_plugins(inventory::InventoryPlugin)
             .add_systems(
                 Update,
                 (
@@ -38,6 +41,7 @@ impl Plugin for PlayerPlugin {
                     (add_players, apply_deferred).chain(),
                     respawn_players,
                     rotate_player_model,
+                    handle_plugin_data,
                 ),
             )
             // Save player after all remaining events have been handled. Avoid dupes and other
@@ -46,6 +50,30 @@ impl Plugin for PlayerPlugin {
     }
 }
 
+/// Data sent to the client's 'movement' plugin. The variants must match the plugin's 'Packet'
+/// enum as it is serialized with bincode.
+#[derive(Serialize)]
+pub enum MovementPacket {
+    /// Changes the player's velocity
+    Velocity(Vec3),
+    /// Notifies the plugin of which models it should collide with.
+    Models(Vec<u32>),
+}
+
+/// Data sent by the client's 'movement' and 'creative' plugins. The variants must match the
+/// plugins' 'ServerPacket' enum as it is serialized with bincode.
+#[derive(Deserialize)]
+enum PluginPacket {
+    /// The player started or stopped sneaking
+    Sneak(bool),
+}
+
+/// Modifier keys the player is holding, as reported by the client's movement plugins.
+#[derive(Component, Default)]
+pub struct Modifiers {
+    pub sneak: bool,
+}
+
 #[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum GameMode {
     Survival,
@@ -79,6 +107,95 @@ impl Hotbar {
     }
 }
 
+/// The player's item storage, in addition to the hotbar.
+#[derive(Component, Serialize, Deserialize, Deref, DerefMut, Clone)]
+pub struct Inventory(Vec<ItemStack>);
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self(vec![ItemStack::default(); 27])
+    }
+}
+
+/// The items placed in the crafting grid of the inventory interface. They are put back in the
+/// inventory when the interface is closed.
+#[derive(Component, Serialize, Deserialize, Deref, DerefMut, Clone)]
+pub struct CraftingGrid(Vec<ItemStack>);
+
+impl CraftingGrid {
+    pub const WIDTH: usize = 3;
+}
+
+impl Default for CraftingGrid {
+    fn default() -> Self {
+        Self(vec![
+            ItemStack::default();
+            CraftingGrid::WIDTH * CraftingGrid::WIDTH
+        ])
+    }
+}
+
+/// Test if any of the item stack fits in the hotbar or inventory.
+pub fn has_room_for(hotbar: &Hotbar, inventory: &Inventory, item_stack: &ItemStack) -> bool {
+    hotbar.iter().chain(inventory.iter()).any(|other| {
+        (other.item() == item_stack.item() && other.remaining_capacity() != 0) || other.is_empty()
+    })
+}
+
+/// Move as much of the item stack as possible into the hotbar, and then the inventory. Stacks
+/// that already have the item are filled before empty ones.
+pub fn insert_item_stack(
+    hotbar: &mut Hotbar,
+    inventory: &mut Inventory,
+    item_stack: &mut ItemStack,
+) {
+    for other in hotbar.iter_mut().chain(inventory.iter_mut()) {
+        if item_stack.is_empty() {
+            return;
+        }
+        if other.item() == item_stack.item() {
+            item_stack.transfer_to(other, u32::MAX);
+        }
+    }
+
+    for other in hotbar.iter_mut().chain(inventory.iter_mut()) {
+        if item_stack.is_empty() {
+            return;
+        }
+        if other.is_empty() {
+            item_stack.transfer_to(other, u32::MAX);
+        }
+    }
+}
+
+/// The item stack a player has picked up from an interface, it is moved along with the cursor
+/// until it is placed somewhere.
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct HeldInterfaceStack(ItemStack);
+
+/// Build an update that shows the item stacks in the item boxes at the interface path.
+pub fn item_box_update(
+    interface_path: &str,
+    item_stacks: &[ItemStack],
+) -> messages::InterfaceItemBoxUpdate {
+    let mut update = messages::InterfaceItemBoxUpdate::default();
+    for (i, item_stack) in item_stacks.iter().enumerate() {
+        if let Some(item) = item_stack.item() {
+            update.add_itembox(
+                interface_path,
+                i as u32,
+                item.id,
+                item_stack.size(),
+                item.properties["durability"].as_u64().map(|v| v as u32),
+                item.properties["description"].as_str(),
+            );
+        } else {
+            update.add_empty_itembox(interface_path, i as u32);
+        }
+    }
+    update
+}
+
 /// Default bundle used for new players.
 #[derive(Bundle)]
 pub struct PlayerBundle {
@@ -86,6 +203,10 @@ pub struct PlayerBundle {
     camera: Camera,
     aabb: Collider,
     hotbar: Hotbar,
+    inventory: Inventory,
+    held_interface_stack: HeldInterfaceStack,
+    crafting_grid: CraftingGrid,
+    modifiers: Modifiers,
     gamemode: GameMode,
 }
 
@@ -96,6 +217,10 @@ impl Default for PlayerBundle {
             camera: Camera::default(),
             aabb: Collider::from_min_max(DVec3::new(-0.3, 0.0, -0.3), DVec3::new(0.3, 1.8, 0.3)),
             hotbar: Hotbar::default(),
+            inventory: Inventory::default(),
+            held_interface_stack: HeldInterfaceStack::default(),
+            crafting_grid: CraftingGrid::default(),
+            modifiers: Modifiers::default(),
             gamemode: GameMode::Survival,
         }
     }
@@ -111,6 +236,9 @@ impl From<PlayerSave> for PlayerBundle {
                 ..default()
             }),
             hotbar: save.hotbar,
+            inventory: save.inventory,
+            held_interface_stack: HeldInterfaceStack(save.held_stack),
+            crafting_grid: save.crafting_grid,
             gamemode: save.game_mode,
             ..default()
         }
@@ -126,6 +254,15 @@ pub struct PlayerSave {
     camera_position: DVec3,
     camera_rotation: DQuat,
     hotbar: Hotbar,
+    // Saves from before the inventory was added don't have one.
+    #[serde(default)]
+    inventory: Inventory,
+    // What the player held in an interface or left in the crafting grid when leaving, kept apart
+    // as there may not be room for it in the inventory.
+    #[serde(default)]
+    held_stack: ItemStack,
+    #[serde(default)]
+    crafting_grid: CraftingGrid,
     game_mode: GameMode,
 }
 
@@ -173,13 +310,22 @@ fn add_players(
     added_players: Query<(Entity, &Player), Added<Player>>,
 ) {
     for (player_entity, player) in added_players.iter() {
-        let bundle = if let Some(save) = PlayerSave::load(&player.username, &database) {
+        let mut bundle = if let Some(save) = PlayerSave::load(&player.username, &database) {
             PlayerBundle::from(save)
         } else {
             respawn_events.send(RespawnEvent { player_entity });
             PlayerBundle::default()
         };
 
+        // The client doesn't know it's holding anything, so it is put back.
+        return_held_stack(
+            &mut commands,
+            &mut bundle.hotbar,
+            &mut bundle.inventory,
+            &mut bundle.held_interface_stack,
+            bundle.transform.translation,
+        );
+
         net.send_one(
             player_entity,
             messages::PlayerPosition {
@@ -220,14 +366,33 @@ fn add_players(
 fn save_player_data(
     database: Res<Database>,
     mut network_events: EventReader<NetworkEvent>,
-    mut players: Query<(&Player, &Transform, &Camera, &Hotbar, &GameMode)>,
+    mut players: Query<(
+        &Player,
+        &Transform,
+        &Camera,
+        &Hotbar,
+        &Inventory,
+        &HeldInterfaceStack,
+        &CraftingGrid,
+        &GameMode,
+    )>,
 ) {
     for network_event in network_events.read() {
         let NetworkEvent::Disconnected { entity } = network_event else {
             continue;
         };
 
-        let Ok((player, transform, camera, hotbar, game_mode)) = players.get_mut(*entity) else {
+        let Ok((
+            player,
+            transform,
+            camera,
+            hotbar,
+            inventory,
+            held_stack,
+            crafting_grid,
+            game_mode,
+        )) = players.get_mut(*entity)
+        else {
             continue;
         };
 
@@ -236,6 +401,9 @@ fn save_player_data(
             camera_position: camera.translation,
             camera_rotation: camera.rotation,
             hotbar: hotbar.clone(),
+            inventory: inventory.clone(),
+            held_stack: (**held_stack).clone(),
+            crafting_grid: crafting_grid.clone(),
             game_mode: *game_mode,
         }
         .save(&player.username, &database);
@@ -335,15 +503,19 @@ fn rotate_player_model(
 
 fn on_gamemode_update(
     net: Res<Server>,
-    player_query: Query<(Entity, &GameMode), Changed<GameMode>>,
+    mut player_query: Query<(Entity, &GameMode, &mut Modifiers), Changed<GameMode>>,
     mut current_movement_function: Local<HashMap<Entity, Option<String>>>,
 ) {
-    for (player_entity, gamemode) in player_query.iter() {
+    for (player_entity, gamemode, mut modifiers) in player_query.iter_mut() {
         let current_movement_function = current_movement_function.entry(player_entity).or_default();
         if let Some(name) = current_movement_function.take() {
             net.send_one(player_entity, messages::Plugin::Disable(name));
         }
 
+        // A disabled plugin can't tell when the keys are released, the enabled one reports them
+        // again if they are still held.
+        modifiers.sneak = false;
+
         match gamemode {
             GameMode::Creative => {
                 net.send_one(
@@ -362,3 +534,37 @@ fn on_gamemode_update(
         }
     }
 }
+
+fn handle_plugin_data(
+    net: Res<Server>,
+    mut players: Query<(&GameMode, &mut Modifiers), With<Player>>,
+    mut plugin_data_events: EventReader<NetworkMessage<messages::PluginData>>,
+) {
+    for plugin_data in plugin_data_events.read() {
+        if plugin_data.plugin != "movement" && plugin_data.plugin != "creative" {
+            continue;
+        }
+
+        let Ok(packet) = bincode::deserialize::<PluginPacket>(&plugin_data.data) else {
+            net.disconnect(plugin_data.player_entity);
+            continue;
+        };
+
+        let Ok((game_mode, mut modifiers)) = players.get_mut(plugin_data.player_entity) else {
+            continue;
+        };
+
+        // Data that arrives from a plugin after it has been disabled is stale.
+        let enabled_plugin = match game_mode {
+            GameMode::Creative => "creative",
+            GameMode::Survival => "movement",
+        };
+        if plugin_data.plugin != enabled_plugin {
+            continue;
+        }
+
+        match packet {
+            PluginPacket::Sneak(sneak) => modifiers.sneak = sneak,
+        }
+    }
+}
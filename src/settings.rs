@@ -22,6 +22,22 @@ pub struct Settings {
     pub pvp: bool,
     /// The max render distance the server will provide for.
     pub render_distance: u32,
+    /// Max amount of scheduled block ticks that are handled each tick. Ticks beyond this are
+    /// delayed to the following ticks.
+    pub scheduled_ticks_per_tick: usize,
+    /// How many blocks in each chunk around the players that are randomly ticked every second.
+    pub random_ticks_per_chunk: u32,
+    /// Seconds before dropped items despawn.
+    pub dropped_item_lifetime: u64,
+    /// Max amount of dropped items in a chunk. When there are more they are merged regardless of
+    /// distance, and the oldest ones removed if that is not enough.
+    pub dropped_items_per_chunk: usize,
+    /// Distance from which players pick up dropped items.
+    pub item_pickup_radius: f64,
+    /// Lets players in creative place blocks in the air when they are not looking at a block.
+    pub creative_air_placement: bool,
+    /// Disconnect players that try to mine blocks out of their reach or sight.
+    pub kick_on_mining_violation: bool,
 }
 
 impl Default for Settings {
@@ -31,6 +47,13 @@ impl Default for Settings {
             seed: 1,
             pvp: false,
             render_distance: 16,
+            scheduled_ticks_per_tick: 4096,
+            random_ticks_per_chunk: 60,
+            dropped_item_lifetime: 300,
+            dropped_items_per_chunk: 64,
+            item_pickup_radius: 3.0,
+            creative_air_placement: false,
+            kick_on_mining_violation: false,
         }
     }
 }
@@ -85,6 +108,77 @@ impl Settings {
                     });
                     server_settings.pvp = value;
                 }
+                "scheduled-ticks-per-tick" => {
+                    let value = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|value| *value > 0)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Server property 'scheduled-ticks-per-tick' must be a number above 0, cannot be: {}",
+                                value
+                            )
+                        });
+                    server_settings.scheduled_ticks_per_tick = value;
+                }
+                "random-ticks-per-chunk" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'random-ticks-per-chunk' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.random_ticks_per_chunk = value;
+                }
+                "dropped-item-lifetime" => {
+                    let value = value.parse::<u64>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'dropped-item-lifetime' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.dropped_item_lifetime = value;
+                }
+                "dropped-items-per-chunk" => {
+                    let value = value.parse::<usize>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'dropped-items-per-chunk' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.dropped_items_per_chunk = value;
+                }
+                "item-pickup-radius" => {
+                    let value = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|value| *value >= 0.0 && *value < 16.0)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Server property 'item-pickup-radius' must be a number between 0 and 16, cannot be: {}",
+                                value
+                            )
+                        });
+                    server_settings.item_pickup_radius = value;
+                }
+                "creative-air-placement" => {
+                    let value = value.parse::<bool>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'creative-air-placement' must be one of 'true/false', cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.creative_air_placement = value;
+                }
+                "kick-on-mining-violation" => {
+                    let value = value.parse::<bool>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'kick-on-mining-violation' must be one of 'true/false', cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.kick_on_mining_violation = value;
+                }
                 _ => {
                     panic!("Invalid setting '{name}' in settings file at line {line}",);
                 }
@@ -100,7 +194,14 @@ impl Settings {
         let settings = Self::default();
         let contents = String::new()
             + "#world-name = " + &settings.database_path + "\n"
-            + "#pvp = " + &settings.pvp.to_string();
+            + "#pvp = " + &settings.pvp.to_string() + "\n"
+            + "#scheduled-ticks-per-tick = " + &settings.scheduled_ticks_per_tick.to_string() + "\n"
+            + "#random-ticks-per-chunk = " + &settings.random_ticks_per_chunk.to_string() + "\n"
+            + "#dropped-item-lifetime = " + &settings.dropped_item_lifetime.to_string() + "\n"
+            + "#dropped-items-per-chunk = " + &settings.dropped_items_per_chunk.to_string() + "\n"
+            + "#item-pickup-radius = " + &settings.item_pickup_radius.to_string() + "\n"
+            + "#creative-air-placement = " + &settings.creative_air_placement.to_string() + "\n"
+            + "#kick-on-mining-violation = " + &settings.kick_on_mining_violation.to_string();
 
         std::fs::write("./server_settings.txt", contents).unwrap();
     }
@@ -0,0 +1,424 @@
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+use fmc::{
+    bevy::{app::AppExit, ecs::system::EntityCommands, math::DVec3},
+    blocks::{BlockId, BlockPosition, Blocks},
+    database::Database,
+    items::ItemStack,
+    prelude::*,
+    world::{chunk::ChunkPosition, ChangedBlockEvent, ChunkSubscriptions, WorldMap},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::items::DroppedItem;
+
+pub(super) struct BlockEntityPlugin;
+impl Plugin for BlockEntityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockEntityRegistry>()
+            .add_systems(Startup, load_block_entities)
+            .add_systems(
+                Update,
+                (track_block_entities, load_subscribed_block_entities).chain(),
+            );
+    }
+}
+
+/// Makes the blocks with the given names into block entities that store 'T'. Changes to the data
+/// are saved to the database when the block entity's chunk is no longer subscribed to, and when
+/// the server shuts down. Until it has been saved once, the data is read from the block's
+/// 'block_data'.
+pub struct BlockEntityDataPlugin<T> {
+    block_names: Vec<&'static str>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> BlockEntityDataPlugin<T> {
+    pub fn new(block_names: &[&'static str]) -> Self {
+        Self {
+            block_names: block_names.to_vec(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: BlockEntityData> Plugin for BlockEntityDataPlugin<T> {
+    fn build(&self, app: &mut App) {
+        let block_names = self.block_names.clone();
+        app.init_resource::<BlockEntityRegistry>()
+            .add_systems(Startup, move |mut registry: ResMut<BlockEntityRegistry>| {
+                let blocks = Blocks::get();
+                for block_name in block_names.iter() {
+                    if !blocks.contains_block(block_name) {
+                        panic!(
+                            "Startup failed while registering block entities. There is no \
+                                block with the name '{}'",
+                            block_name
+                        );
+                    }
+                    registry.insert(blocks.get_id(block_name), spawn_block_entity::<T>);
+                }
+            })
+            .add_systems(
+                Update,
+                (
+                    save_block_entity_data::<T>.before(load_subscribed_block_entities),
+                    drop_block_entity_contents::<T>,
+                ),
+            )
+            .add_systems(Last, save_block_entity_data_on_exit::<T>);
+    }
+}
+
+/// State kept for a block, e.g. the items in a chest.
+pub trait BlockEntityData: Component + Serialize + DeserializeOwned + Default {
+    /// Items that are dropped when the block is broken.
+    fn drops(&self) -> Vec<ItemStack> {
+        Vec::new()
+    }
+}
+
+/// Added to all block entities
+#[derive(Component)]
+pub struct BlockEntity {
+    position: BlockPosition,
+    block_id: BlockId,
+}
+
+impl BlockEntity {
+    pub fn position(&self) -> BlockPosition {
+        self.position
+    }
+
+    pub fn block_id(&self) -> BlockId {
+        self.block_id
+    }
+}
+
+// Marks block entities whose block has been broken, they drop their contents and are despawned.
+#[derive(Component)]
+pub(super) struct Removed;
+
+// Marks block entities whose data has changed since it was last saved.
+#[derive(Component)]
+struct Unsaved;
+
+// Inserts the data of a block entity, deserialized from the block data if it has any.
+type SpawnFunction = fn(&mut EntityCommands, Option<&[u8]>);
+
+fn spawn_block_entity<T: BlockEntityData>(
+    entity_commands: &mut EntityCommands,
+    data: Option<&[u8]>,
+) {
+    let data = data
+        .and_then(|data| serde_json::from_slice::<T>(data).ok())
+        .unwrap_or_default();
+    entity_commands.insert(data);
+}
+
+#[derive(Resource, Deref, DerefMut, Default)]
+struct BlockEntityRegistry(HashMap<BlockId, SpawnFunction>);
+
+/// The entities of the blocks that have one. Block entities are only spawned while their chunk
+/// is subscribed to.
+#[derive(Resource, Default)]
+pub struct BlockEntities {
+    entities: HashMap<BlockPosition, Entity>,
+    // Position of all block entities, including the ones that aren't spawned.
+    positions: HashMap<ChunkPosition, HashSet<BlockPosition>>,
+    // Chunks whose block entities are spawned
+    loaded: HashSet<ChunkPosition>,
+}
+
+impl BlockEntities {
+    pub fn get(&self, position: &BlockPosition) -> Option<Entity> {
+        self.entities.get(position).copied()
+    }
+}
+
+fn load_block_entities(mut commands: Commands, database: Res<Database>) {
+    let conn = database.get_write_connection();
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS block_entities (
+            x INTEGER NOT NULL,
+            y INTEGER NOT NULL,
+            z INTEGER NOT NULL,
+            data BLOB,
+            PRIMARY KEY (x, y, z)
+        )",
+        [],
+    )
+    .unwrap();
+
+    let mut block_entities = BlockEntities::default();
+
+    let mut stmt = conn.prepare("SELECT x, y, z FROM block_entities").unwrap();
+    let positions = stmt
+        .query_map([], |row| {
+            Ok(BlockPosition::new(row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap();
+    for position in positions {
+        let position = position.unwrap();
+        block_entities
+            .positions
+            .entry(ChunkPosition::from(position))
+            .or_default()
+            .insert(position);
+    }
+
+    commands.insert_resource(block_entities);
+}
+
+fn track_block_entities(
+    mut commands: Commands,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    registry: Res<BlockEntityRegistry>,
+    mut block_entities: ResMut<BlockEntities>,
+    block_entity_query: Query<&BlockEntity>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+) {
+    if changed_blocks.is_empty() {
+        return;
+    }
+
+    let conn = database.get_write_connection();
+
+    for changed_block in changed_blocks.read() {
+        let position = changed_block.position;
+        let chunk_position = ChunkPosition::from(position);
+
+        if let Some(entity) = block_entities.get(&position) {
+            // Changes to the block's state or data keep the entity.
+            if block_entity_query
+                .get(entity)
+                .is_ok_and(|block_entity| block_entity.block_id == changed_block.to.0)
+            {
+                continue;
+            }
+
+            commands.entity(entity).insert(Removed);
+            block_entities.entities.remove(&position);
+            if let Some(positions) = block_entities.positions.get_mut(&chunk_position) {
+                positions.remove(&position);
+            }
+            conn.execute(
+                "DELETE FROM block_entities WHERE x = ? AND y = ? AND z = ?",
+                [position.x, position.y, position.z],
+            )
+            .unwrap();
+        }
+
+        let Some(spawn) = registry.get(&changed_block.to.0) else {
+            continue;
+        };
+
+        block_entities
+            .positions
+            .entry(chunk_position)
+            .or_default()
+            .insert(position);
+        // New block entities start out with the data of the block.
+        conn.execute(
+            "INSERT OR REPLACE INTO block_entities VALUES (?,?,?,NULL)",
+            [position.x, position.y, position.z],
+        )
+        .unwrap();
+
+        // Chunks that aren't loaded spawn the entity when they are, otherwise nothing would
+        // despawn it.
+        if !block_entities.loaded.contains(&chunk_position) {
+            continue;
+        }
+
+        let mut entity_commands = commands.spawn(BlockEntity {
+            position,
+            block_id: changed_block.to.0,
+        });
+        spawn(
+            &mut entity_commands,
+            world_map
+                .get_block_data(position)
+                .map(|data| data.as_slice()),
+        );
+        block_entities
+            .entities
+            .insert(position, entity_commands.id());
+    }
+}
+
+// Spawns the block entities of chunks that are subscribed to, and despawns them when the chunk
+// no longer is. Their data has been saved before they are despawned, so nothing is lost.
+fn load_subscribed_block_entities(
+    mut commands: Commands,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    registry: Res<BlockEntityRegistry>,
+    mut block_entities: ResMut<BlockEntities>,
+) {
+    let has_subscribers = |chunk_position: &ChunkPosition| {
+        chunk_subscriptions
+            .get_subscribers(chunk_position)
+            .is_some_and(|subscribers| !subscribers.is_empty())
+    };
+
+    let block_entities = block_entities.as_mut();
+
+    let unsubscribed: Vec<ChunkPosition> = block_entities
+        .loaded
+        .iter()
+        .filter(|chunk_position| !has_subscribers(chunk_position))
+        .copied()
+        .collect();
+    for chunk_position in unsubscribed {
+        block_entities.loaded.remove(&chunk_position);
+        let Some(positions) = block_entities.positions.get(&chunk_position) else {
+            continue;
+        };
+        for position in positions.iter() {
+            if let Some(entity) = block_entities.entities.remove(position) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    let conn = database.get_read_connection();
+
+    for (chunk_position, positions) in block_entities.positions.iter() {
+        if block_entities.loaded.contains(chunk_position) || !has_subscribers(chunk_position) {
+            continue;
+        }
+
+        // Wait for the chunk to be loaded
+        if positions
+            .iter()
+            .any(|position| world_map.get_block(*position).is_none())
+        {
+            continue;
+        }
+
+        block_entities.loaded.insert(*chunk_position);
+
+        for position in positions.iter() {
+            if block_entities.entities.contains_key(position) {
+                continue;
+            }
+
+            let block_id = world_map.get_block(*position).unwrap();
+            // The block may have been changed while the server was not running, this is cleaned
+            // up when the block changes.
+            let Some(spawn) = registry.get(&block_id) else {
+                continue;
+            };
+
+            let saved_data: Option<Vec<u8>> = conn
+                .query_row(
+                    "SELECT data FROM block_entities WHERE x = ? AND y = ? AND z = ?",
+                    [position.x, position.y, position.z],
+                    |row| row.get(0),
+                )
+                .ok()
+                .flatten();
+
+            let mut entity_commands = commands.spawn(BlockEntity {
+                position: *position,
+                block_id,
+            });
+            spawn(
+                &mut entity_commands,
+                saved_data
+                    .as_deref()
+                    .or_else(|| world_map.get_block_data(*position).map(Vec::as_slice)),
+            );
+            block_entities
+                .entities
+                .insert(*position, entity_commands.id());
+        }
+    }
+}
+
+// Marks block entities whose data has changed, and saves the ones whose chunk is about to be
+// unloaded. The data is kept out of the chunk's block data, as changing it would count as a block
+// change.
+fn save_block_entity_data<T: BlockEntityData>(
+    mut commands: Commands,
+    database: Res<Database>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    block_entities: Query<(Entity, &BlockEntity, Ref<T>, Has<Unsaved>), Without<Removed>>,
+) {
+    let conn = database.get_write_connection();
+
+    for (entity, block_entity, data, unsaved) in block_entities.iter() {
+        let changed = data.is_changed() && !data.is_added();
+        if !changed && !unsaved {
+            continue;
+        }
+
+        let chunk_position = ChunkPosition::from(block_entity.position);
+        if chunk_subscriptions
+            .get_subscribers(&chunk_position)
+            .is_some_and(|subscribers| !subscribers.is_empty())
+        {
+            if changed && !unsaved {
+                commands.entity(entity).insert(Unsaved);
+            }
+            continue;
+        }
+
+        save_data(&conn, block_entity.position, &*data);
+        commands.entity(entity).remove::<Unsaved>();
+    }
+}
+
+fn save_block_entity_data_on_exit<T: BlockEntityData>(
+    database: Res<Database>,
+    block_entities: Query<(&BlockEntity, &T), (With<Unsaved>, Without<Removed>)>,
+    mut exit_events: EventReader<AppExit>,
+) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+
+    let conn = database.get_write_connection();
+    for (block_entity, data) in block_entities.iter() {
+        save_data(&conn, block_entity.position, data);
+    }
+}
+
+fn save_data<T: BlockEntityData>(conn: &rusqlite::Connection, position: BlockPosition, data: &T) {
+    conn.execute(
+        "UPDATE block_entities SET data = ? WHERE x = ? AND y = ? AND z = ?",
+        rusqlite::params![
+            serde_json::to_vec(data).unwrap(),
+            position.x,
+            position.y,
+            position.z
+        ],
+    )
+    .unwrap();
+}
+
+fn drop_block_entity_contents<T: BlockEntityData>(
+    mut commands: Commands,
+    removed_block_entities: Query<(Entity, &BlockEntity, &T), With<Removed>>,
+) {
+    for (entity, block_entity, data) in removed_block_entities.iter() {
+        commands.entity(entity).despawn();
+
+        let center = block_entity.position.as_dvec3() + DVec3::splat(0.5);
+        for item_stack in data.drops() {
+            if item_stack.is_empty() {
+                continue;
+            }
+            commands.spawn((
+                DroppedItem::new(item_stack),
+                Transform::from_translation(center),
+            ));
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use fmc::{
+    items::ItemStack,
+    networking::{NetworkMessage, Server},
+    players::Player,
+    prelude::*,
+    protocol::messages,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    items::ItemUseSystems,
+    players::{
+        item_box_update, read_item_moves, return_held_stack, HandInteractions, HeldInterfaceStack,
+        Hotbar, Inventory,
+    },
+};
+
+use super::{block_entities::Removed, BlockEntityData, BlockEntityDataPlugin};
+
+const CHEST_SIZE: usize = 27;
+
+pub(super) struct ChestPlugin;
+impl Plugin for ChestPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(BlockEntityDataPlugin::<Chest>::new(&["chest"]))
+            .add_systems(
+                Update,
+                (
+                    open_chests.after(ItemUseSystems),
+                    close_chests,
+                    move_items,
+                    send_chest_updates,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Storage of a chest block
+#[derive(Component, Serialize, Deserialize)]
+#[require(HandInteractions)]
+pub struct Chest {
+    items: Vec<ItemStack>,
+}
+
+impl Default for Chest {
+    fn default() -> Self {
+        Self {
+            items: vec![ItemStack::default(); CHEST_SIZE],
+        }
+    }
+}
+
+impl BlockEntityData for Chest {
+    fn drops(&self) -> Vec<ItemStack> {
+        self.items.clone()
+    }
+}
+
+/// The chest whose interface the player has open.
+#[derive(Component)]
+struct OpenChest(Entity);
+
+fn open_chests(
+    mut commands: Commands,
+    net: Res<Server>,
+    mut chests: Query<(Entity, &Chest, &mut HandInteractions)>,
+    hotbars: Query<&Hotbar>,
+) {
+    for (chest_entity, chest, mut interactions) in chests.iter_mut() {
+        for player_entity in interactions.read() {
+            let Ok(hotbar) = hotbars.get(player_entity) else {
+                continue;
+            };
+
+            commands
+                .entity(player_entity)
+                .insert(OpenChest(chest_entity));
+
+            net.send_one(
+                player_entity,
+                item_box_update("chest/storage", &chest.items),
+            );
+            net.send_one(player_entity, item_box_update("chest/hotbar", hotbar));
+            net.send_one(
+                player_entity,
+                messages::InterfaceVisibilityUpdate {
+                    interface_path: "chest".to_owned(),
+                    visible: true,
+                },
+            );
+        }
+    }
+}
+
+fn close_chests(
+    mut commands: Commands,
+    net: Res<Server>,
+    chests: Query<(), With<Chest>>,
+    mut players: Query<
+        (
+            Entity,
+            &OpenChest,
+            &mut Hotbar,
+            &mut Inventory,
+            &mut HeldInterfaceStack,
+            &GlobalTransform,
+        ),
+        With<Player>,
+    >,
+    mut close_events: EventReader<NetworkMessage<messages::InterfaceClose>>,
+) {
+    let mut closed: Vec<Entity> = close_events
+        .read()
+        .filter(|close_event| close_event.interface_path == "chest")
+        .map(|close_event| close_event.player_entity)
+        .collect();
+
+    // The chest has been broken or unloaded while it was open.
+    for (player_entity, open_chest, ..) in players.iter() {
+        if chests.get(open_chest.0).is_err() {
+            net.send_one(
+                player_entity,
+                messages::InterfaceVisibilityUpdate {
+                    interface_path: "chest".to_owned(),
+                    visible: false,
+                },
+            );
+            closed.push(player_entity);
+        }
+    }
+
+    for player_entity in closed {
+        let Ok((_, _, mut hotbar, mut inventory, mut held_stack, transform)) =
+            players.get_mut(player_entity)
+        else {
+            continue;
+        };
+
+        commands.entity(player_entity).remove::<OpenChest>();
+
+        return_held_stack(
+            &mut commands,
+            &mut hotbar,
+            &mut inventory,
+            &mut held_stack,
+            transform.translation(),
+        );
+    }
+}
+
+fn move_items(
+    net: Res<Server>,
+    // Broken chests drop their items, they can't be taken as well.
+    mut chests: Query<&mut Chest, Without<Removed>>,
+    mut players: Query<(&OpenChest, &mut Hotbar, &mut HeldInterfaceStack), With<Player>>,
+    mut take_events: EventReader<NetworkMessage<messages::InterfaceTakeItem>>,
+    mut place_events: EventReader<NetworkMessage<messages::InterfacePlaceItem>>,
+) {
+    let item_moves = read_item_moves(&mut take_events, &mut place_events, "chest/");
+
+    for (player_entity, mut item_moves) in item_moves {
+        let Ok((open_chest, mut hotbar, mut held_stack)) = players.get_mut(player_entity) else {
+            continue;
+        };
+
+        let Ok(mut chest) = chests.get_mut(open_chest.0) else {
+            continue;
+        };
+
+        while let Some(item_move) = item_moves.next(&held_stack) {
+            let item_stack = match item_move.interface_path.as_str() {
+                "chest/storage" => chest.items.get_mut(item_move.index),
+                "chest/hotbar" => hotbar.get_mut(item_move.index),
+                _ => None,
+            };
+
+            let Some(item_stack) = item_stack else {
+                net.disconnect(player_entity);
+                break;
+            };
+
+            if !item_move.apply(item_stack, &mut held_stack) {
+                // Triggers change detection so both interfaces are sent again.
+                chest.set_changed();
+                hotbar.set_changed();
+            }
+        }
+    }
+}
+
+fn send_chest_updates(
+    net: Res<Server>,
+    chests: Query<Ref<Chest>>,
+    players: Query<(Entity, &OpenChest, Ref<Hotbar>)>,
+) {
+    for (player_entity, open_chest, hotbar) in players.iter() {
+        let Ok(chest) = chests.get(open_chest.0) else {
+            continue;
+        };
+
+        // Everyone that has the chest open sees the changes.
+        if chest.is_changed() {
+            net.send_one(
+                player_entity,
+                item_box_update("chest/storage", &chest.items),
+            );
+        }
+
+        if hotbar.is_changed() {
+            net.send_one(player_entity, item_box_update("chest/hotbar", &hotbar));
+        }
+    }
+}
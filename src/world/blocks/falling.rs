@@ -0,0 +1,253 @@
+use std::{collections::HashSet, time::Duration};
+
+use fmc::{
+    bevy::math::DVec3,
+    blocks::{BlockId, BlockPosition, BlockState, Blocks},
+    items::{ItemStack, Items},
+    models::{Model, Models},
+    physics::{Collider, Physics},
+    prelude::*,
+    world::{BlockUpdate, ChangedBlockEvent, WorldMap},
+};
+
+use crate::items::DroppedItem;
+
+use super::{
+    properties::load_server_block_configs, BlockTickRegistry, BlockTickSystems, BlockTicks,
+    ScheduledTicks, ServerBlockConfigs,
+};
+
+// Ticks between a block losing its support and it starting to fall.
+const FALL_DELAY: u32 = 2;
+// Blocks that haven't landed after falling this long are dropped as items where they are, e.g.
+// when they fall into a chunk that isn't loaded.
+const MAX_FALL_TIME: Duration = Duration::from_secs(30);
+
+pub(super) struct FallingBlocksPlugin;
+impl Plugin for FallingBlocksPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            register_gravity_blocks.after(load_server_block_configs),
+        )
+        .add_systems(
+            Update,
+            (
+                schedule_falling,
+                start_falling.after(BlockTickSystems),
+                land_falling_blocks,
+            ),
+        );
+    }
+}
+
+// Handles the scheduled ticks of all gravity blocks.
+#[derive(Component)]
+struct GravityBlockTicks;
+
+/// A block that has lost its support and is falling as a model.
+#[derive(Component)]
+#[require(Transform)]
+struct FallingBlock {
+    block_id: BlockId,
+    block_state: Option<BlockState>,
+    fall_time: Duration,
+}
+
+fn register_gravity_blocks(
+    mut commands: Commands,
+    server_block_configs: Res<ServerBlockConfigs>,
+    mut block_tick_registry: ResMut<BlockTickRegistry>,
+) {
+    let entity = commands
+        .spawn((BlockTicks::default(), GravityBlockTicks))
+        .id();
+
+    for (block_id, config) in server_block_configs.iter() {
+        if config.gravity {
+            block_tick_registry.insert(*block_id, entity);
+        }
+    }
+}
+
+fn schedule_falling(
+    server_block_configs: Res<ServerBlockConfigs>,
+    mut scheduled_ticks: ResMut<ScheduledTicks>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+) {
+    let blocks = Blocks::get();
+
+    for changed_block in changed_blocks.read() {
+        // Either the block that was placed has nothing below it, or the block that held up the
+        // block above it was removed. Removing the block above in turn makes whatever is above
+        // that fall, so whole columns come down.
+        let (position, block_id) = if server_block_configs.get(&changed_block.to.0).gravity
+            && changed_block
+                .bottom
+                .is_some_and(|(block_id, _)| blocks.get_config(&block_id).replaceable)
+        {
+            (changed_block.position, changed_block.to.0)
+        } else if let Some((block_id, _)) = changed_block.top.filter(|(block_id, _)| {
+            server_block_configs.get(block_id).gravity
+                && blocks.get_config(&changed_block.to.0).replaceable
+        }) {
+            (changed_block.position + IVec3::Y, block_id)
+        } else {
+            continue;
+        };
+
+        scheduled_ticks.schedule(position, block_id, FALL_DELAY, 0);
+    }
+}
+
+fn start_falling(
+    mut commands: Commands,
+    items: Res<Items>,
+    models: Res<Models>,
+    world_map: Res<WorldMap>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    mut gravity_block_ticks: Query<&mut BlockTicks, With<GravityBlockTicks>>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+
+    let mut ticks = gravity_block_ticks.single_mut();
+
+    // Where blocks have been moved to this frame. The world map isn't updated until the block
+    // updates are applied, so without this two blocks could be moved to the same position.
+    let mut moved_to = HashSet::new();
+
+    for position in ticks.read() {
+        // The block may have been replaced or given new support since the tick was scheduled.
+        let Some(block_id) = world_map
+            .get_block(position)
+            .filter(|block_id| server_block_configs.get(block_id).gravity)
+        else {
+            continue;
+        };
+        if !world_map
+            .get_block(position + IVec3::NEG_Y)
+            .is_some_and(|block_id| blocks.get_config(&block_id).replaceable)
+        {
+            continue;
+        }
+        let block_state = world_map.get_block_state(position);
+
+        // The block is shown using the model of the item it drops. Without one there is nothing
+        // to show it falling, and it is moved straight to where it lands.
+        let Some((item_id, _)) = blocks.get_config(&block_id).drop(None) else {
+            let mut landing_position = position;
+            loop {
+                let below = landing_position + IVec3::NEG_Y;
+                if moved_to.contains(&below)
+                    || !world_map
+                        .get_block(below)
+                        .is_some_and(|block_id| blocks.get_config(&block_id).replaceable)
+                {
+                    break;
+                }
+                landing_position = below;
+            }
+
+            if landing_position == position {
+                continue;
+            }
+            moved_to.insert(landing_position);
+
+            block_updates.send(BlockUpdate::Replace {
+                position,
+                block_id: air,
+                block_state: None,
+                block_data: None,
+            });
+            block_updates.send(BlockUpdate::Replace {
+                position: landing_position,
+                block_id,
+                block_state,
+                block_data: None,
+            });
+            continue;
+        };
+
+        block_updates.send(BlockUpdate::Replace {
+            position,
+            block_id: air,
+            block_state: None,
+            block_data: None,
+        });
+
+        let model_config = models.get_by_id(items.get_config(&item_id).model_id);
+        let block_center = position.as_dvec3() + DVec3::splat(0.5);
+        commands.spawn((
+            FallingBlock {
+                block_id,
+                block_state,
+                fall_time: Duration::ZERO,
+            },
+            Model::Asset(model_config.id),
+            Collider::Aabb(model_config.aabb.clone()),
+            Transform::from_translation(block_center - model_config.aabb.center),
+            Physics {
+                enabled: true,
+                ..default()
+            },
+        ));
+    }
+}
+
+fn land_falling_blocks(
+    mut commands: Commands,
+    time: Res<Time>,
+    items: Res<Items>,
+    world_map: Res<WorldMap>,
+    mut falling_blocks: Query<(Entity, &mut FallingBlock, &Transform, &Collider, &Physics)>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let blocks = Blocks::get();
+
+    // Positions blocks have landed in this frame, see 'start_falling'.
+    let mut landed = HashSet::new();
+
+    for (entity, mut falling_block, transform, collider, physics) in falling_blocks.iter_mut() {
+        falling_block.fall_time += time.delta();
+
+        let timed_out = falling_block.fall_time > MAX_FALL_TIME;
+        if !physics.grounded.y && !timed_out {
+            continue;
+        }
+
+        commands.entity(entity).despawn();
+
+        let center = transform.translation + collider.as_aabb().center;
+        let position = BlockPosition::from(center);
+
+        // When landing on a partial block like a slab, the center may be inside its cell, the
+        // block then lands in the cell above it.
+        let landing_position = [position, position + IVec3::Y]
+            .into_iter()
+            .filter(|_| !timed_out)
+            .find(|position| {
+                !landed.contains(position)
+                    && world_map
+                        .get_block(*position)
+                        .is_some_and(|block_id| blocks.get_config(&block_id).replaceable)
+            });
+
+        if let Some(position) = landing_position {
+            landed.insert(position);
+            block_updates.send(BlockUpdate::Replace {
+                position,
+                block_id: falling_block.block_id,
+                block_state: falling_block.block_state,
+                block_data: None,
+            });
+        } else if let Some((item_id, count)) = blocks.get_config(&falling_block.block_id).drop(None)
+        {
+            commands.spawn((
+                DroppedItem::new(ItemStack::new(items.get_config(&item_id), count)),
+                Transform::from_translation(center),
+            ));
+        }
+    }
+}
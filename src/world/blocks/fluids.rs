@@ -0,0 +1,1355 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    ops::{Index, IndexMut},
+    sync::{Arc, Mutex},
+};
+
+use fmc::{
+    bevy::math::DVec3,
+    blocks::{BlockFace, BlockId, BlockPosition, BlockRotation, BlockState, Blocks},
+    networking::Server,
+    physics::Physics,
+    players::Player,
+    prelude::*,
+    protocol::messages,
+    world::{
+        chunk::{Chunk, ChunkPosition},
+        BlockUpdate, ChangedBlockEvent, WorldMap,
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    items::DroppedItem,
+    players::{GameMode, MovementPacket},
+};
+
+use super::{BlockTickRegistry, BlockTickSystems, BlockTicks, ScheduledTicks};
+
+pub const FLUID_PATH: &str = "./assets/server/fluids/";
+
+/// How fast a current accelerates what floats in it.
+const FLOW_ACCELERATION: f64 = 8.0;
+/// Dropped items are not accelerated beyond this speed.
+const FLOW_SPEED: f64 = 2.5;
+/// Players are pushed at this interval to avoid sending a packet every tick.
+const PLAYER_PUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+pub(super) struct FluidPlugin;
+impl Plugin for FluidPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(UnsettledFluids::default())
+            .add_systems(Startup, load_fluids)
+            .add_systems(
+                Update,
+                (
+                    (activate_unsettled_fluids, schedule_fluid_ticks).chain(),
+                    tick_fluids.after(BlockTickSystems),
+                    push_dropped_items,
+                    push_players,
+                ),
+            );
+    }
+}
+
+/// Fluid blocks that were generated next to air and should start flowing once their chunk has
+/// been loaded. The terrain generator holds a clone and fills it as chunks are generated. Fluids
+/// next to air in another chunk are found when the later of the two chunks is loaded.
+#[derive(Resource, Clone, Default)]
+pub struct UnsettledFluids(Arc<Mutex<HashMap<ChunkPosition, Vec<BlockPosition>>>>);
+
+impl UnsettledFluids {
+    pub fn insert(&self, chunk_position: ChunkPosition, positions: Vec<BlockPosition>) {
+        self.0.lock().unwrap().insert(chunk_position, positions);
+    }
+}
+
+// Generated fluids never send a ChangedBlockEvent, so they do not spread on their own. Replacing
+// the unsettled blocks with themselves makes 'schedule_fluid_ticks' pick them up.
+fn activate_unsettled_fluids(
+    world_map: Res<WorldMap>,
+    fluids: Res<Fluids>,
+    unsettled_fluids: Res<UnsettledFluids>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let air = Blocks::get().get_id("air");
+
+    let mut activate = |position: BlockPosition| {
+        let Some(block_id) = world_map.get_block(position) else {
+            return;
+        };
+
+        block_updates.send(BlockUpdate::Replace {
+            position,
+            block_id,
+            block_state: world_map.get_block_state(position),
+            block_data: None,
+        });
+    };
+
+    let mut unsettled = unsettled_fluids.0.lock().unwrap();
+    unsettled.retain(|chunk_position, positions| {
+        let origin = BlockPosition::from(*chunk_position);
+
+        // The chunk is generated before it is added to the world, wait for it.
+        if world_map.get_block(origin).is_none() {
+            return true;
+        }
+
+        for position in positions.drain(..) {
+            activate(position);
+        }
+
+        // Pairs of blocks on each side of the chunk's border. If the neighbouring chunk isn't
+        // loaded yet, it does the check when it is.
+        let size = Chunk::SIZE as i32;
+        for a in 0..size {
+            for b in 0..size {
+                for (inside, outside) in [
+                    (IVec3::new(0, a, b), IVec3::new(-1, a, b)),
+                    (IVec3::new(size - 1, a, b), IVec3::new(size, a, b)),
+                    (IVec3::new(a, b, 0), IVec3::new(a, b, -1)),
+                    (IVec3::new(a, b, size - 1), IVec3::new(a, b, size)),
+                    (IVec3::new(a, 0, b), IVec3::new(a, -1, b)),
+                    (IVec3::new(a, size - 1, b), IVec3::new(a, size, b)),
+                ] {
+                    let inside = origin + inside;
+                    let outside = origin + outside;
+                    let (Some(inside_block), Some(outside_block)) =
+                        (world_map.get_block(inside), world_map.get_block(outside))
+                    else {
+                        continue;
+                    };
+
+                    // Fluids flow sideways and down, never up.
+                    if fluids.is_fluid(inside_block)
+                        && outside_block == air
+                        && outside.y <= inside.y
+                    {
+                        activate(inside);
+                    } else if fluids.is_fluid(outside_block)
+                        && inside_block == air
+                        && inside.y <= outside.y
+                    {
+                        activate(outside);
+                    }
+                }
+            }
+        }
+
+        false
+    });
+}
+
+/// The format of the fluid definitions at FLUID_PATH. The name of the fluid is the name of the
+/// file.
+#[derive(Deserialize)]
+struct FluidJson {
+    /// Name used to look up the fluid's blocks. Flowing blocks are named after their shape and
+    /// level, e.g. 'straight_water_5' or 'diagonal_water_corner_up_3'. Sources are
+    /// 'surface_<block_name>' and 'subsurface_<block_name>', and a full flowing block is
+    /// 'still_<block_name>_10'.
+    block_name: String,
+    /// How many blocks the fluid can flow horizontally from a source, at most 9.
+    spread_distance: u8,
+    /// Ticks between each time the fluid flows, see 'ScheduledTicks'.
+    tick_delay: u32,
+    /// If flowing blocks between two sources should turn into a source.
+    #[serde(default)]
+    infinite_source: bool,
+    #[serde(default)]
+    interactions: Vec<InteractionJson>,
+}
+
+/// When this fluid touches the other fluid, the other fluid's block is replaced by 'result'.
+#[derive(Deserialize)]
+struct InteractionJson {
+    fluid: String,
+    result: String,
+}
+
+// The shapes the flowing blocks can have, with the corner levels of the highest level block of
+// each shape. Blocks are named '<shape>_<block_name><suffix>_<level>', where the level is the
+// lowest corner.
+const SHAPES: [(&str, &str, [FluidLevel; 4]); 6] = [
+    (
+        "still",
+        "",
+        [
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+        ],
+    ),
+    (
+        "straight",
+        "",
+        [
+            FluidLevel::Eight,
+            FluidLevel::Eight,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+        ],
+    ),
+    (
+        "diagonal",
+        "",
+        [
+            FluidLevel::Seven,
+            FluidLevel::Eight,
+            FluidLevel::Nine,
+            FluidLevel::Eight,
+        ],
+    ),
+    (
+        "diagonal",
+        "_corner_up",
+        [
+            FluidLevel::Eight,
+            FluidLevel::Eight,
+            FluidLevel::Nine,
+            FluidLevel::Eight,
+        ],
+    ),
+    (
+        "diagonal",
+        "_corner_down",
+        [
+            FluidLevel::Eight,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+        ],
+    ),
+    (
+        "tilted",
+        "",
+        [
+            FluidLevel::Nine,
+            FluidLevel::Eight,
+            FluidLevel::Nine,
+            FluidLevel::Eight,
+        ],
+    ),
+];
+
+fn load_fluids(
+    mut commands: Commands,
+    blocks: Res<Blocks>,
+    mut block_tick_registry: ResMut<BlockTickRegistry>,
+) {
+    let directory = std::fs::read_dir(FLUID_PATH).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read the fluid directory at '{}'.\nError: {}",
+            FLUID_PATH, e
+        )
+    });
+
+    let mut fluids = Fluids::default();
+    let mut interactions = Vec::new();
+
+    for dir_entry in directory {
+        let file_path = dir_entry.unwrap().path();
+        let Some(name) = file_path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let name = name.to_owned();
+
+        let file = std::fs::File::open(&file_path).unwrap();
+        let json: FluidJson = serde_json::from_reader(file).unwrap_or_else(|e| {
+            panic!(
+                "Failed to read the fluid definition at '{}'.\nError: {}",
+                file_path.display(),
+                e
+            )
+        });
+
+        let validate_block = |block_name: &str| -> BlockId {
+            if !blocks.contains_block(block_name) {
+                panic!(
+                    "Startup failed while loading the fluids. The fluid '{}' needs a block with \
+                    the name '{}', but no block by that name exists.",
+                    name, block_name
+                );
+            }
+            blocks.get_id(block_name)
+        };
+
+        let mut fluid = Fluid {
+            name: name.clone(),
+            fluid_to_block: HashMap::new(),
+            block_to_fluid: HashMap::new(),
+            infinite_source: json.infinite_source,
+            tick_delay: json.tick_delay,
+            interactions: Vec::new(),
+        };
+
+        let min_level = 10 - json.spread_distance.clamp(1, 9);
+        for (shape, suffix, corners) in SHAPES {
+            let max_level = *corners.iter().min().unwrap() as u8;
+            let block_ids = (min_level..=max_level)
+                .rev()
+                .map(|level| {
+                    validate_block(&format!(
+                        "{}_{}{}_{}",
+                        shape, json.block_name, suffix, level
+                    ))
+                })
+                .collect();
+            fluid.add(
+                FluidBlock {
+                    corners,
+                    is_source: false,
+                },
+                block_ids,
+            );
+        }
+
+        // Have to add the two source block types manually, as well as the full still block
+        // because it works the same way as subsurface.
+        let surface = validate_block(&format!("surface_{}", json.block_name));
+        fluid.fluid_to_block.insert(SOURCE, (surface, None));
+        fluid.block_to_fluid.insert((surface, None), SOURCE);
+
+        let subsurface = validate_block(&format!("subsurface_{}", json.block_name));
+        let mut subsurface_source = TEN.clone();
+        subsurface_source.is_source = true;
+        fluid
+            .fluid_to_block
+            .insert(subsurface_source.clone(), (subsurface, None));
+        fluid
+            .block_to_fluid
+            .insert((subsurface, None), subsurface_source);
+
+        let full = validate_block(&format!("still_{}_10", json.block_name));
+        fluid.fluid_to_block.insert(TEN, (full, None));
+        fluid.block_to_fluid.insert((full, None), TEN);
+
+        // This is for removal of the fluid
+        fluid
+            .fluid_to_block
+            .insert(FluidBlock::default(), (blocks.get_id("air"), None));
+
+        for interaction in json.interactions {
+            interactions.push((
+                fluids.fluids.len(),
+                interaction.fluid,
+                validate_block(&interaction.result),
+            ));
+        }
+
+        // All of the fluid's ticks are scheduled for its source block, whatever block is at the
+        // position.
+        let entity = commands
+            .spawn((BlockTicks::default(), FluidTicks(fluids.fluids.len())))
+            .id();
+        block_tick_registry.insert(fluid.source_block().0, entity);
+
+        fluids
+            .fluid_blocks
+            .extend(fluid.block_to_fluid.keys().map(|(block_id, _)| *block_id));
+        fluids.fluids.push(fluid);
+    }
+
+    for (index, other_fluid, result) in interactions {
+        let Some(other_index) = fluids
+            .fluids
+            .iter()
+            .position(|fluid| fluid.name == other_fluid)
+        else {
+            panic!(
+                "Startup failed while loading the fluids. The fluid '{}' interacts with the fluid \
+                '{}', but no fluid by that name exists. Make sure it is present at '{}'",
+                fluids.fluids[index].name, other_fluid, FLUID_PATH
+            );
+        };
+        fluids.fluids[index]
+            .interactions
+            .push((other_index, result));
+    }
+
+    commands.insert_resource(fluids);
+}
+
+#[derive(Resource, Default)]
+pub struct Fluids {
+    fluids: Vec<Fluid>,
+    // Every block that is part of a fluid
+    fluid_blocks: HashSet<BlockId>,
+}
+
+impl Fluids {
+    pub fn get(&self, name: &str) -> Option<&Fluid> {
+        self.fluids.iter().find(|fluid| fluid.name == name)
+    }
+
+    /// The fluid the block is a source of
+    pub fn get_by_source(&self, block: &(BlockId, Option<BlockState>)) -> Option<&Fluid> {
+        self.fluids.iter().find(|fluid| fluid.is_source(block))
+    }
+
+    /// Check if the block belongs to any of the fluids
+    pub fn is_fluid(&self, block_id: BlockId) -> bool {
+        self.fluid_blocks.contains(&block_id)
+    }
+
+    /// The direction of the current at the position, if it is inside a flowing fluid.
+    pub fn flow(&self, world_map: &WorldMap, position: DVec3) -> Option<DVec3> {
+        let block_position = BlockPosition::from(position);
+        let block = (
+            world_map.get_block(block_position)?,
+            world_map.get_block_state(block_position),
+        );
+        let flow = self.fluids.iter().find_map(|fluid| fluid.flow(&block))?;
+        (flow != DVec3::ZERO).then_some(flow)
+    }
+}
+
+// Handles the scheduled ticks of the fluid at this index
+#[derive(Component)]
+struct FluidTicks(usize);
+
+// Updates collected from the ticks of a fluid, applied together when all have been handled.
+#[derive(Default)]
+struct PendingUpdates {
+    updates: HashMap<BlockPosition, FluidBlock>,
+    // Blocks that should be replaced because of an interaction between two fluids.
+    interactions: HashMap<BlockPosition, BlockId>,
+}
+
+impl PendingUpdates {
+    fn get(&self, position: &BlockPosition) -> Option<&FluidBlock> {
+        self.updates.get(position)
+    }
+
+    fn insert(&mut self, position: BlockPosition, fluid_block: FluidBlock) {
+        self.updates.insert(position, fluid_block);
+    }
+
+    fn entry(&mut self, position: BlockPosition) -> Entry<'_, BlockPosition, FluidBlock> {
+        self.updates.entry(position)
+    }
+
+    fn insert_interaction(&mut self, position: BlockPosition, block_id: BlockId) {
+        self.interactions.insert(position, block_id);
+    }
+
+    fn remove(&mut self, position: &BlockPosition) {
+        self.updates.remove(position);
+        self.interactions.remove(position);
+    }
+}
+
+pub struct Fluid {
+    pub name: String,
+    fluid_to_block: HashMap<FluidBlock, (BlockId, Option<BlockState>)>,
+    block_to_fluid: HashMap<(BlockId, Option<BlockState>), FluidBlock>,
+    infinite_source: bool,
+    tick_delay: u32,
+    // Index of other fluid, and the block it turns into when this fluid touches it.
+    interactions: Vec<(usize, BlockId)>,
+}
+
+impl Fluid {
+    /// Check if the block is a source of this fluid
+    pub fn is_source(&self, block: &(BlockId, Option<BlockState>)) -> bool {
+        self.block_to_fluid
+            .get(block)
+            .is_some_and(|fluid_block| fluid_block.is_source)
+    }
+
+    /// The block that is placed when creating a new source
+    pub fn source_block(&self) -> (BlockId, Option<BlockState>) {
+        self.fluid_to_block[&SOURCE]
+    }
+
+    /// Normalized direction the fluid flows in, from the high corners of the block towards the
+    /// low corners. Zero if the surface is level.
+    pub fn flow(&self, block: &(BlockId, Option<BlockState>)) -> Option<DVec3> {
+        self.block_to_fluid
+            .get(block)
+            .map(|fluid_block| fluid_block.flow())
+    }
+
+    fn contains(&self, block: &(BlockId, Option<BlockState>)) -> bool {
+        self.block_to_fluid.contains_key(block)
+    }
+
+    #[track_caller]
+    fn add(&mut self, mut fluid_block: FluidBlock, block_ids: Vec<BlockId>) {
+        for block_id in block_ids {
+            self.fluid_to_block
+                .insert(fluid_block.clone(), (block_id, None));
+            self.block_to_fluid
+                .insert((block_id, None), fluid_block.clone());
+
+            if fluid_block[Corner::Left] == fluid_block[Corner::Right]
+                && fluid_block[Corner::Left] == fluid_block[Corner::FarRight]
+                && fluid_block[Corner::Left] == fluid_block[Corner::FarLeft]
+            {
+                fluid_block[Corner::Left] = fluid_block[Corner::Left].decrement();
+                fluid_block[Corner::Right] = fluid_block[Corner::Right].decrement();
+                fluid_block[Corner::FarRight] = fluid_block[Corner::FarRight].decrement();
+                fluid_block[Corner::FarLeft] = fluid_block[Corner::FarLeft].decrement();
+
+                continue;
+            }
+
+            for i in 1..4 {
+                let rotation = BlockRotation::from(i);
+                self.fluid_to_block.insert(
+                    fluid_block.rotate(rotation),
+                    (block_id, Some(BlockState::new().with_rotation(rotation))),
+                );
+                self.block_to_fluid.insert(
+                    (block_id, Some(BlockState::new().with_rotation(rotation))),
+                    fluid_block.rotate(rotation),
+                );
+            }
+
+            fluid_block[Corner::Left] = fluid_block[Corner::Left].decrement();
+            fluid_block[Corner::Right] = fluid_block[Corner::Right].decrement();
+            fluid_block[Corner::FarRight] = fluid_block[Corner::FarRight].decrement();
+            fluid_block[Corner::FarLeft] = fluid_block[Corner::FarLeft].decrement();
+        }
+    }
+}
+
+#[derive(Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+enum FluidLevel {
+    #[default]
+    Zero,
+    One,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+}
+
+impl FluidLevel {
+    #[track_caller]
+    fn decrement(self) -> Self {
+        match self {
+            Self::Zero => unreachable!(),
+            Self::One => Self::Zero,
+            Self::Two => Self::One,
+            Self::Three => Self::Two,
+            Self::Four => Self::Three,
+            Self::Five => Self::Four,
+            Self::Six => Self::Five,
+            Self::Seven => Self::Six,
+            Self::Eight => Self::Seven,
+            Self::Nine => Self::Eight,
+            Self::Ten => Self::Nine,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Corner {
+    Left = 0,
+    Right,
+    FarRight,
+    FarLeft,
+}
+
+impl Corner {
+    fn rotate(&self, rotation: BlockRotation) -> Self {
+        let new = (*self as usize + rotation as usize) % 4;
+        match new {
+            0 => Corner::Left,
+            1 => Corner::Right,
+            2 => Corner::FarRight,
+            3 => Corner::FarLeft,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Default, PartialEq, Eq, Hash, Clone, Debug)]
+struct FluidBlock {
+    corners: [FluidLevel; 4],
+    is_source: bool,
+}
+
+impl FluidBlock {
+    fn flow(&self) -> DVec3 {
+        let mut flow = DVec3::ZERO;
+        for (corner, block_faces) in [
+            (Corner::Left, [BlockFace::Left, BlockFace::Front]),
+            (Corner::Right, [BlockFace::Right, BlockFace::Front]),
+            (Corner::FarRight, [BlockFace::Right, BlockFace::Back]),
+            (Corner::FarLeft, [BlockFace::Left, BlockFace::Back]),
+        ] {
+            // Points from the center of the block towards the corner
+            let direction = block_faces
+                .iter()
+                .map(|block_face| {
+                    block_face
+                        .shift_position(BlockPosition::new(0, 0, 0))
+                        .as_dvec3()
+                })
+                .sum::<DVec3>();
+            flow -= direction * self[corner] as u8 as f64;
+        }
+        flow.normalize_or_zero()
+    }
+
+    fn rotate(&self, rotation: BlockRotation) -> Self {
+        match rotation {
+            BlockRotation::Right => FluidBlock {
+                corners: [
+                    self.corners[Corner::FarLeft as usize],
+                    self.corners[Corner::Left as usize],
+                    self.corners[Corner::Right as usize],
+                    self.corners[Corner::FarRight as usize],
+                ],
+                is_source: self.is_source,
+            },
+            BlockRotation::Back => FluidBlock {
+                corners: [
+                    self.corners[Corner::FarRight as usize],
+                    self.corners[Corner::FarLeft as usize],
+                    self.corners[Corner::Left as usize],
+                    self.corners[Corner::Right as usize],
+                ],
+                is_source: self.is_source,
+            },
+            BlockRotation::Left => FluidBlock {
+                corners: [
+                    self.corners[Corner::Right as usize],
+                    self.corners[Corner::FarRight as usize],
+                    self.corners[Corner::FarLeft as usize],
+                    self.corners[Corner::Left as usize],
+                ],
+                is_source: self.is_source,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn update_corner(&mut self, corner: Corner, fluid_level: FluidLevel) {
+        if self.corners[corner as usize] == fluid_level
+            || self.corners[corner as usize] == FluidLevel::Ten
+        {
+            return;
+        }
+
+        self.corners[corner as usize] = fluid_level;
+
+        if self[corner.rotate(BlockRotation::Right)] < self[corner] {
+            self[corner.rotate(BlockRotation::Right)] = self[corner].decrement();
+        }
+
+        if self[corner.rotate(BlockRotation::Left)] < self[corner] {
+            self[corner.rotate(BlockRotation::Left)] = self[corner].decrement();
+        }
+
+        if self[corner.rotate(BlockRotation::Back)] < self[corner.rotate(BlockRotation::Right)]
+            || self[corner.rotate(BlockRotation::Back)] < self[corner.rotate(BlockRotation::Left)]
+        {
+            self[corner.rotate(BlockRotation::Back)] = self[corner.rotate(BlockRotation::Right)]
+                .max(self[corner.rotate(BlockRotation::Left)])
+                .decrement();
+        }
+    }
+}
+
+impl Index<Corner> for FluidBlock {
+    type Output = FluidLevel;
+    fn index(&self, index: Corner) -> &Self::Output {
+        &self.corners[index as usize]
+    }
+}
+
+impl IndexMut<Corner> for FluidBlock {
+    fn index_mut(&mut self, index: Corner) -> &mut Self::Output {
+        &mut self.corners[index as usize]
+    }
+}
+
+const SOURCE: FluidBlock = FluidBlock {
+    corners: [
+        FluidLevel::Nine,
+        FluidLevel::Nine,
+        FluidLevel::Nine,
+        FluidLevel::Nine,
+    ],
+    is_source: true,
+};
+
+const TEN: FluidBlock = FluidBlock {
+    corners: [
+        FluidLevel::Ten,
+        FluidLevel::Ten,
+        FluidLevel::Ten,
+        FluidLevel::Ten,
+    ],
+    is_source: false,
+};
+
+#[derive(Debug)]
+struct ChangedBlockAsFluid {
+    pub to: Option<FluidBlock>,
+    pub top: Option<FluidBlock>,
+    pub bottom: Option<FluidBlock>,
+    pub back: Option<FluidBlock>,
+    pub back_right: Option<FluidBlock>,
+    pub back_left: Option<FluidBlock>,
+    pub right: Option<FluidBlock>,
+    pub left: Option<FluidBlock>,
+    pub front: Option<FluidBlock>,
+    pub front_right: Option<FluidBlock>,
+    pub front_left: Option<FluidBlock>,
+}
+
+impl ChangedBlockAsFluid {
+    fn new(changed_block: &Neighbourhood, fluid: &Fluid) -> Self {
+        Self {
+            to: fluid.block_to_fluid.get(&changed_block.to).cloned(),
+            top: changed_block
+                .top
+                .as_ref()
+                .and_then(|top| fluid.block_to_fluid.get(top).cloned()),
+            bottom: changed_block
+                .bottom
+                .as_ref()
+                .and_then(|bottom| fluid.block_to_fluid.get(bottom).cloned()),
+            back: changed_block
+                .back
+                .as_ref()
+                .and_then(|back| fluid.block_to_fluid.get(back).cloned()),
+            back_right: changed_block
+                .back_right
+                .as_ref()
+                .and_then(|back_right| fluid.block_to_fluid.get(back_right).cloned()),
+            back_left: changed_block
+                .back_left
+                .as_ref()
+                .and_then(|back_left| fluid.block_to_fluid.get(back_left).cloned()),
+            right: changed_block
+                .right
+                .as_ref()
+                .and_then(|right| fluid.block_to_fluid.get(right).cloned()),
+            left: changed_block
+                .left
+                .as_ref()
+                .and_then(|left| fluid.block_to_fluid.get(left).cloned()),
+            front: changed_block
+                .front
+                .as_ref()
+                .and_then(|front| fluid.block_to_fluid.get(front).cloned()),
+            front_right: changed_block
+                .front_right
+                .as_ref()
+                .and_then(|front_right| fluid.block_to_fluid.get(front_right).cloned()),
+            front_left: changed_block
+                .front_left
+                .as_ref()
+                .and_then(|front_left| fluid.block_to_fluid.get(front_left).cloned()),
+        }
+    }
+}
+
+impl Index<BlockFace> for ChangedBlockAsFluid {
+    type Output = Option<FluidBlock>;
+    fn index(&self, index: BlockFace) -> &Self::Output {
+        match index {
+            BlockFace::Front => &self.front,
+            BlockFace::Back => &self.back,
+            BlockFace::Right => &self.right,
+            BlockFace::Left => &self.left,
+            BlockFace::Top => &self.top,
+            BlockFace::Bottom => &self.bottom,
+        }
+    }
+}
+
+impl Index<[BlockFace; 2]> for ChangedBlockAsFluid {
+    type Output = Option<FluidBlock>;
+    #[track_caller]
+    fn index(&self, index: [BlockFace; 2]) -> &Self::Output {
+        match index {
+            [BlockFace::Front, BlockFace::Left] => &self.front_left,
+            [BlockFace::Left, BlockFace::Front] => &self.front_left,
+            [BlockFace::Front, BlockFace::Right] => &self.front_right,
+            [BlockFace::Right, BlockFace::Front] => &self.front_right,
+            [BlockFace::Back, BlockFace::Left] => &self.back_left,
+            [BlockFace::Left, BlockFace::Back] => &self.back_left,
+            [BlockFace::Back, BlockFace::Right] => &self.back_right,
+            [BlockFace::Right, BlockFace::Back] => &self.back_right,
+            _ => panic!("Tried to index with non-horizontal blockfaces."),
+        }
+    }
+}
+
+// Fluids that are generated next to air are marked as unsettled by the terrain generator, see
+// 'activate_unsettled_fluids'.
+fn schedule_fluid_ticks(
+    fluids: Res<Fluids>,
+    mut scheduled_ticks: ResMut<ScheduledTicks>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+) {
+    for changed_block in changed_blocks.read() {
+        let neighbours = [
+            changed_block.top,
+            changed_block.bottom,
+            changed_block.back,
+            changed_block.back_right,
+            changed_block.back_left,
+            changed_block.right,
+            changed_block.left,
+            changed_block.front,
+            changed_block.front_right,
+            changed_block.front_left,
+        ];
+
+        for fluid in fluids.fluids.iter() {
+            if fluid.contains(&changed_block.to)
+                || neighbours.iter().any(|neighbour| {
+                    neighbour
+                        .as_ref()
+                        .is_some_and(|block| fluid.contains(block))
+                })
+            {
+                scheduled_ticks.schedule(
+                    changed_block.position,
+                    fluid.source_block().0,
+                    fluid.tick_delay,
+                    0,
+                );
+            }
+        }
+    }
+}
+
+fn tick_fluids(
+    world_map: Res<WorldMap>,
+    fluids: Res<Fluids>,
+    mut fluid_ticks: Query<(&mut BlockTicks, &FluidTicks)>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let air = Blocks::get().get_id("air");
+    let mut interactions = Vec::new();
+
+    for (mut ticks, fluid_index) in fluid_ticks.iter_mut() {
+        let fluid = &fluids.fluids[fluid_index.0];
+        let mut pending = PendingUpdates::default();
+
+        for position in ticks.read() {
+            let Some(neighbourhood) = Neighbourhood::new(&world_map, position) else {
+                continue;
+            };
+            spread(fluid, &fluids.fluids, &neighbourhood, &mut pending);
+        }
+
+        block_updates.send_batch(pending.updates.drain().map(|(position, fluid_block)| {
+            // TODO: The idea is that it's not supposed to generate invalid fluid states, but it
+            // does often when trying to remove the fluid at edges. Ending up with states like
+            // [Zero, Zero, One, One] and variations. This is probably what introduces the
+            // flickering that sometimes happen.
+            //let (block_id, block_state) = fluid.fluid_to_block[&fluid_block];
+            let (block_id, block_state) = match fluid.fluid_to_block.get(&fluid_block) {
+                Some(k) => *k,
+                None => (air, None),
+            };
+            BlockUpdate::Replace {
+                position,
+                block_id,
+                block_state,
+                block_data: None,
+            }
+        }));
+
+        interactions.extend(pending.interactions.drain());
+    }
+
+    // Sent last so they aren't overwritten by the fluids flowing into each other.
+    block_updates.send_batch(interactions.into_iter().map(|(position, block_id)| {
+        BlockUpdate::Replace {
+            position,
+            block_id,
+            block_state: None,
+            block_data: None,
+        }
+    }));
+}
+
+// A block and the blocks around it, read from the world when the block is ticked.
+struct Neighbourhood {
+    position: BlockPosition,
+    to: (BlockId, Option<BlockState>),
+    top: Option<(BlockId, Option<BlockState>)>,
+    bottom: Option<(BlockId, Option<BlockState>)>,
+    back: Option<(BlockId, Option<BlockState>)>,
+    back_right: Option<(BlockId, Option<BlockState>)>,
+    back_left: Option<(BlockId, Option<BlockState>)>,
+    right: Option<(BlockId, Option<BlockState>)>,
+    left: Option<(BlockId, Option<BlockState>)>,
+    front: Option<(BlockId, Option<BlockState>)>,
+    front_right: Option<(BlockId, Option<BlockState>)>,
+    front_left: Option<(BlockId, Option<BlockState>)>,
+}
+
+impl Neighbourhood {
+    fn new(world_map: &WorldMap, position: BlockPosition) -> Option<Self> {
+        let get = |block_faces: &[BlockFace]| {
+            let position = block_faces.iter().fold(position, |position, block_face| {
+                block_face.shift_position(position)
+            });
+            world_map
+                .get_block(position)
+                .map(|block_id| (block_id, world_map.get_block_state(position)))
+        };
+
+        Some(Self {
+            position,
+            to: get(&[])?,
+            top: get(&[BlockFace::Top]),
+            bottom: get(&[BlockFace::Bottom]),
+            back: get(&[BlockFace::Back]),
+            back_right: get(&[BlockFace::Back, BlockFace::Right]),
+            back_left: get(&[BlockFace::Back, BlockFace::Left]),
+            right: get(&[BlockFace::Right]),
+            left: get(&[BlockFace::Left]),
+            front: get(&[BlockFace::Front]),
+            front_right: get(&[BlockFace::Front, BlockFace::Right]),
+            front_left: get(&[BlockFace::Front, BlockFace::Left]),
+        })
+    }
+}
+
+impl Index<BlockFace> for Neighbourhood {
+    type Output = Option<(BlockId, Option<BlockState>)>;
+    fn index(&self, index: BlockFace) -> &Self::Output {
+        match index {
+            BlockFace::Top => &self.top,
+            BlockFace::Bottom => &self.bottom,
+            BlockFace::Back => &self.back,
+            BlockFace::Right => &self.right,
+            BlockFace::Left => &self.left,
+            BlockFace::Front => &self.front,
+        }
+    }
+}
+
+// Collects the updates a tick causes for a single fluid.
+fn spread(
+    fluid: &Fluid,
+    fluids: &[Fluid],
+    changed_block: &Neighbourhood,
+    pending: &mut PendingUpdates,
+) {
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+
+    // If there's an update waiting to be sent, but the block is changed, the update is stale
+    pending.remove(&changed_block.position);
+
+    interact(fluid, fluids, changed_block, pending);
+
+    let change_as_fluid = ChangedBlockAsFluid::new(changed_block, fluid);
+
+    let mut fluid_block = if let Some(to) = &change_as_fluid.to {
+        if to.is_source {
+            to.clone()
+        } else {
+            let new_max = to.corners.iter().max().unwrap().decrement();
+            FluidBlock {
+                corners: [
+                    to[Corner::Left].min(new_max),
+                    to[Corner::Right].min(new_max),
+                    to[Corner::FarRight].min(new_max),
+                    to[Corner::FarLeft].min(new_max),
+                ],
+                is_source: false,
+            }
+        }
+    } else {
+        FluidBlock::default()
+    };
+
+    if change_as_fluid.top.is_some() {
+        fluid_block.corners = [
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+            FluidLevel::Nine,
+        ]
+    } else if !fluid_block.is_source {
+        for (corner, block_faces) in [
+            (Corner::Left, [BlockFace::Left, BlockFace::Front]),
+            (Corner::Left, [BlockFace::Front, BlockFace::Left]),
+            (Corner::Right, [BlockFace::Right, BlockFace::Front]),
+            (Corner::Right, [BlockFace::Front, BlockFace::Right]),
+            (Corner::FarRight, [BlockFace::Right, BlockFace::Back]),
+            (Corner::FarRight, [BlockFace::Back, BlockFace::Right]),
+            (Corner::FarLeft, [BlockFace::Left, BlockFace::Back]),
+            (Corner::FarLeft, [BlockFace::Back, BlockFace::Left]),
+        ] {
+            if let Some(adjacent_fluid_block) = &change_as_fluid[block_faces[0]] {
+                let (corner_one, corner_two) = match (corner, block_faces[0]) {
+                    (Corner::Left, BlockFace::Left) => (Corner::Right, Corner::Left),
+                    (Corner::Left, BlockFace::Front) => (Corner::FarLeft, Corner::Left),
+                    (Corner::Right, BlockFace::Right) => (Corner::Left, Corner::Right),
+                    (Corner::Right, BlockFace::Front) => (Corner::FarRight, Corner::Right),
+                    (Corner::FarRight, BlockFace::Right) => (Corner::FarLeft, Corner::FarRight),
+                    (Corner::FarRight, BlockFace::Back) => (Corner::Right, Corner::FarRight),
+                    (Corner::FarLeft, BlockFace::Left) => (Corner::FarRight, Corner::FarLeft),
+                    (Corner::FarLeft, BlockFace::Back) => (Corner::Left, Corner::FarLeft),
+                    _ => unreachable!(),
+                };
+                if adjacent_fluid_block.is_source {
+                    fluid_block.update_corner(corner, FluidLevel::Nine);
+                } else {
+                    fluid_block.update_corner(
+                        corner,
+                        fluid_block[corner]
+                            .max(adjacent_fluid_block[corner_one].decrement())
+                            .max(adjacent_fluid_block[corner_two].decrement()),
+                    );
+                }
+            }
+
+            if change_as_fluid[block_faces[0]].is_some() && change_as_fluid[block_faces].is_some() {
+                let diagonal_fluid_block = change_as_fluid[block_faces].as_ref().unwrap();
+                let (corner_near, corner_far) = match block_faces {
+                    // left corner
+                    [BlockFace::Left, BlockFace::Front] => (Corner::FarRight, Corner::Right),
+                    [BlockFace::Front, BlockFace::Left] => (Corner::FarLeft, Corner::FarRight),
+                    // right corner
+                    [BlockFace::Right, BlockFace::Front] => (Corner::FarLeft, Corner::Left),
+                    [BlockFace::Front, BlockFace::Right] => (Corner::FarLeft, Corner::FarRight),
+                    // far right corner
+                    [BlockFace::Right, BlockFace::Back] => (Corner::FarLeft, Corner::Left),
+                    [BlockFace::Back, BlockFace::Right] => (Corner::Left, Corner::Right),
+                    // far left corner
+                    [BlockFace::Left, BlockFace::Back] => (Corner::FarRight, Corner::Right),
+                    [BlockFace::Back, BlockFace::Left] => (Corner::Left, Corner::Right),
+                    _ => unreachable!(),
+                };
+                if diagonal_fluid_block.is_source {
+                    fluid_block.update_corner(corner, FluidLevel::Nine);
+                } else {
+                    fluid_block.update_corner(
+                        corner,
+                        fluid_block[corner]
+                            .max(diagonal_fluid_block[corner_near].decrement())
+                            .max(diagonal_fluid_block[corner_far].decrement()),
+                    );
+                }
+            }
+        }
+    }
+
+    // Flowing fluid that lies between two sources and rests on something that can hold it
+    // becomes a source itself.
+    if fluid.infinite_source
+        && !fluid_block.is_source
+        && (change_as_fluid.to.is_some() || changed_block.to.0 == air)
+        && (change_as_fluid
+            .bottom
+            .as_ref()
+            .is_some_and(|bottom| bottom.is_source)
+            || changed_block
+                .bottom
+                .is_some_and(|bottom| blocks.get_config(&bottom.0).is_solid()))
+        && [
+            BlockFace::Front,
+            BlockFace::Right,
+            BlockFace::Back,
+            BlockFace::Left,
+        ]
+        .into_iter()
+        .filter(|block_face| {
+            change_as_fluid[*block_face]
+                .as_ref()
+                .is_some_and(|adjacent| adjacent.is_source)
+        })
+        .count()
+            >= 2
+    {
+        fluid_block = SOURCE;
+    }
+
+    if fluid_block != FluidBlock::default()
+        && fluid_block.corners.iter().any(|c| c == &FluidLevel::Zero)
+    {
+        fluid_block.corners = FluidBlock::default().corners;
+    }
+
+    if fluid_block == FluidBlock::default() && change_as_fluid.to.is_none() {
+        return;
+    }
+
+    for block_face in [
+        BlockFace::Front,
+        BlockFace::Right,
+        BlockFace::Back,
+        BlockFace::Left,
+    ] {
+        //let (orthogonal_one, orthogonal_two) = match block_face {
+        //    BlockFace::Front | BlockFace::Back => (BlockFace::Right, BlockFace::Left),
+        //    BlockFace::Left | BlockFace::Right => (BlockFace::Front, BlockFace::Back),
+        //    _ => unreachable!(),
+        //};
+        let to_corners = match block_face {
+            BlockFace::Left => [Corner::Right, Corner::FarRight],
+            BlockFace::Right => [Corner::Left, Corner::FarLeft],
+            BlockFace::Front => [Corner::FarLeft, Corner::FarRight],
+            BlockFace::Back => [Corner::Left, Corner::Right],
+            _ => unreachable!(),
+        };
+        let from_corners = match block_face {
+            BlockFace::Left => [Corner::Left, Corner::FarLeft],
+            BlockFace::Right => [Corner::Right, Corner::FarRight],
+            BlockFace::Front => [Corner::Left, Corner::Right],
+            BlockFace::Back => [Corner::FarLeft, Corner::FarRight],
+            _ => unreachable!(),
+        };
+        let position = block_face.shift_position(changed_block.position);
+
+        if let Some(adjacent_fluid_block) = &change_as_fluid[block_face] {
+            //if change_as_fluid.to.is_some()
+            //    || (change_as_fluid[orthogonal_one].is_some()
+            //        && change_as_fluid[[orthogonal_one, block_face]].is_some())
+            //    || (change_as_fluid[orthogonal_two].is_some()
+            //        && change_as_fluid[[orthogonal_two, block_face]].is_some())
+            //{
+            let mut update = pending
+                .get(&position)
+                .unwrap_or(adjacent_fluid_block)
+                .clone();
+            update.update_corner(to_corners[0], fluid_block[from_corners[0]]);
+            update.update_corner(to_corners[1], fluid_block[from_corners[1]]);
+
+            if &update != adjacent_fluid_block {
+                pending.insert(position, update);
+            }
+            //}
+        } else if changed_block[block_face].is_some_and(|b| b.0 == air)
+            && changed_block.bottom.is_some_and(|b| b.0 != air)
+            && change_as_fluid.bottom.is_none()
+            && fluid_block[from_corners[0]] > FluidLevel::One
+            && fluid_block[from_corners[1]] > FluidLevel::One
+        {
+            let update = pending.entry(position).or_insert(FluidBlock::default());
+            if update != &TEN {
+                update.update_corner(to_corners[0], fluid_block[from_corners[0]]);
+                update.update_corner(to_corners[1], fluid_block[from_corners[1]]);
+            }
+        }
+    }
+
+    for (block_faces, corner_to, corner_from) in [
+        (
+            [BlockFace::Front, BlockFace::Left],
+            Corner::FarRight,
+            Corner::Left,
+        ),
+        (
+            [BlockFace::Front, BlockFace::Right],
+            Corner::FarLeft,
+            Corner::Right,
+        ),
+        (
+            [BlockFace::Back, BlockFace::Right],
+            Corner::Left,
+            Corner::FarRight,
+        ),
+        (
+            [BlockFace::Back, BlockFace::Left],
+            Corner::Right,
+            Corner::FarLeft,
+        ),
+    ] {
+        if let Some(diagonal_fluid_block) = &change_as_fluid[block_faces] {
+            if change_as_fluid[block_faces[0]].is_some()
+                || change_as_fluid[block_faces[1]].is_some()
+            {
+                let position = block_faces[0]
+                    .shift_position(block_faces[1].shift_position(changed_block.position));
+                let mut update = pending
+                    .get(&position)
+                    .unwrap_or(diagonal_fluid_block)
+                    .clone();
+                update.update_corner(corner_to, fluid_block[corner_from]);
+                if &update != diagonal_fluid_block {
+                    pending.insert(position, update);
+                }
+            }
+        }
+    }
+
+    if let Some(bottom) = &change_as_fluid.bottom {
+        if fluid_block == FluidBlock::default() {
+            pending.insert(
+                changed_block.position - IVec3::Y,
+                FluidBlock {
+                    corners: [
+                        FluidLevel::Nine,
+                        FluidLevel::Nine,
+                        FluidLevel::Nine,
+                        FluidLevel::Nine,
+                    ],
+                    is_source: bottom.is_source,
+                },
+            );
+        }
+    }
+
+    if changed_block.bottom.is_some_and(|block| block.0 == air) {
+        pending.insert(changed_block.position - IVec3::Y, TEN.clone());
+    } else if let Some(bottom) = change_as_fluid.bottom {
+        if bottom.corners != TEN.corners {
+            let mut new = TEN.clone();
+            new.is_source = bottom.is_source;
+            pending.insert(changed_block.position - IVec3::Y, new);
+        }
+    }
+
+    if changed_block.to.0 == air
+        || change_as_fluid
+            .to
+            .is_some_and(|to| to != fluid_block && to.corners != TEN.corners)
+    {
+        if change_as_fluid.top.is_some() {
+            pending.insert(changed_block.position, TEN.clone());
+        } else {
+            pending.insert(changed_block.position, fluid_block);
+        }
+    }
+}
+
+// When the fluid touches a fluid it interacts with, the other fluid's block is converted.
+fn interact(
+    fluid: &Fluid,
+    fluids: &[Fluid],
+    changed_block: &Neighbourhood,
+    pending: &mut PendingUpdates,
+) {
+    if fluid.interactions.is_empty() {
+        return;
+    }
+
+    let neighbours = [
+        (BlockFace::Top, changed_block.top),
+        (BlockFace::Bottom, changed_block.bottom),
+        (BlockFace::Front, changed_block.front),
+        (BlockFace::Back, changed_block.back),
+        (BlockFace::Left, changed_block.left),
+        (BlockFace::Right, changed_block.right),
+    ];
+
+    if fluid.contains(&changed_block.to) {
+        for (block_face, neighbour) in neighbours {
+            let Some(neighbour) = neighbour else {
+                continue;
+            };
+
+            for (other_fluid, result) in fluid.interactions.iter() {
+                if fluids[*other_fluid].contains(&neighbour) {
+                    let position = block_face.shift_position(changed_block.position);
+                    pending.insert_interaction(position, *result);
+                }
+            }
+        }
+    } else {
+        let touches_fluid = neighbours.iter().any(|(_, neighbour)| {
+            neighbour
+                .as_ref()
+                .is_some_and(|block| fluid.contains(block))
+        });
+        if !touches_fluid {
+            return;
+        }
+
+        for (other_fluid, result) in fluid.interactions.iter() {
+            if fluids[*other_fluid].contains(&changed_block.to) {
+                pending.insert_interaction(changed_block.position, *result);
+            }
+        }
+    }
+}
+
+fn push_dropped_items(
+    world_map: Res<WorldMap>,
+    fluids: Res<Fluids>,
+    time: Res<Time>,
+    mut dropped_items: Query<(&Transform, &mut Physics), With<DroppedItem>>,
+) {
+    for (transform, mut physics) in dropped_items.iter_mut() {
+        let Some(flow) = fluids.flow(&world_map, transform.translation) else {
+            continue;
+        };
+
+        let speed = physics.velocity.dot(flow);
+        if speed < FLOW_SPEED {
+            let acceleration = FLOW_ACCELERATION * time.delta_secs_f64();
+            physics.velocity += flow * acceleration.min(FLOW_SPEED - speed);
+        }
+    }
+}
+
+// The server doesn't simulate player movement, the velocity is added through the client's
+// movement plugin instead. As the server doesn't know the player's velocity, it is estimated
+// from how far the player moved since the last push, so that players are capped at the same
+// speed as dropped items.
+fn push_players(
+    net: Res<Server>,
+    world_map: Res<WorldMap>,
+    fluids: Res<Fluids>,
+    time: Res<Time>,
+    players: Query<(Entity, &Transform, &GameMode), With<Player>>,
+    mut last_push: Local<std::time::Duration>,
+    mut last_positions: Local<HashMap<Entity, DVec3>>,
+) {
+    let interval = time.elapsed() - *last_push;
+    if interval < PLAYER_PUSH_INTERVAL {
+        return;
+    }
+    *last_push = time.elapsed();
+
+    let mut positions = HashMap::with_capacity(last_positions.len());
+
+    for (player_entity, transform, game_mode) in players.iter() {
+        // Creative movement can't be pushed
+        if *game_mode != GameMode::Survival {
+            continue;
+        }
+
+        let Some(flow) = fluids.flow(&world_map, transform.translation) else {
+            continue;
+        };
+
+        positions.insert(player_entity, transform.translation);
+
+        let speed = match last_positions.get(&player_entity) {
+            Some(last_position) => {
+                (transform.translation - *last_position).dot(flow) / interval.as_secs_f64()
+            }
+            None => 0.0,
+        };
+        if speed >= FLOW_SPEED {
+            continue;
+        }
+
+        let acceleration = FLOW_ACCELERATION * interval.as_secs_f64();
+        let velocity = flow * acceleration.min(FLOW_SPEED - speed);
+        net.send_one(
+            player_entity,
+            messages::PluginData {
+                plugin: "movement".to_owned(),
+                data: bincode::serialize(&MovementPacket::Velocity(velocity.as_vec3())).unwrap(),
+            },
+        );
+    }
+
+    *last_positions = positions;
+}
@@ -0,0 +1,410 @@
+use fmc::{
+    blocks::Blocks,
+    items::{ItemStack, Items},
+    networking::{NetworkMessage, Server},
+    players::Player,
+    prelude::*,
+    protocol::messages,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::{CHAT_FONT_SIZE, CHAT_TEXT_COLOR},
+    items::{ItemUseSystems, Recipes},
+    players::{
+        item_box_update, read_item_moves, return_held_stack, HandInteractions, HeldInterfaceStack,
+        Hotbar, Inventory,
+    },
+};
+
+use super::{
+    block_entities::Removed, BlockEntities, BlockEntity, BlockEntityData, BlockEntityDataPlugin,
+    BlockTickRegistry, BlockTickSystems, BlockTicks, ScheduledTicks, TICKS_PER_SECOND,
+};
+
+// How many ticks there are between each time a furnace is updated.
+const FURNACE_TICK_INTERVAL: u32 = 10;
+
+pub(super) struct FurnacePlugin;
+impl Plugin for FurnacePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(BlockEntityDataPlugin::<Furnace>::new(&["furnace"]))
+            .add_systems(Startup, register_furnace_ticks)
+            .add_systems(
+                Update,
+                (
+                    tick_furnaces.after(BlockTickSystems),
+                    open_furnaces.after(ItemUseSystems),
+                    close_furnaces,
+                    move_items,
+                    schedule_furnace_ticks,
+                    send_furnace_updates,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Smelts the item in the input slot using the fuel in the fuel slot. How long an item takes is
+/// set by its smelting recipe, and how long a fuel item burns by the 'burn_time' property in its
+/// item config, both in seconds.
+#[derive(Component, Serialize, Deserialize, Default)]
+#[require(HandInteractions)]
+pub struct Furnace {
+    input: ItemStack,
+    fuel: ItemStack,
+    output: ItemStack,
+    // Ticks left until the fuel has burnt out
+    burn_time: u32,
+    // How many ticks the fuel burns for in total
+    burn_duration: u32,
+    // How many ticks the input has been cooking for
+    cook_progress: u32,
+}
+
+impl BlockEntityData for Furnace {
+    fn drops(&self) -> Vec<ItemStack> {
+        vec![self.input.clone(), self.fuel.clone(), self.output.clone()]
+    }
+}
+
+impl Furnace {
+    // The output of smelting the input, and how many ticks it takes. None if the input can't be
+    // smelted or there is no room for the output.
+    fn smelting(&self, items: &Items, recipes: &Recipes) -> Option<(ItemStack, u32)> {
+        let recipe = recipes.get_smelting(&self.input.item()?.id)?;
+        let output = recipe.output(items);
+
+        if !self.output.is_empty()
+            && (self.output.item() != output.item()
+                || self.output.remaining_capacity() < output.size())
+        {
+            return None;
+        }
+
+        let cook_time = recipe.cook_time().as_secs_f32() * TICKS_PER_SECOND as f32;
+        Some((output, (cook_time as u32).max(1)))
+    }
+
+    fn is_active(&self, items: &Items, recipes: &Recipes) -> bool {
+        self.burn_time > 0
+            || (burn_time(items, &self.fuel).is_some() && self.smelting(items, recipes).is_some())
+    }
+
+    // Returns true if anything changed
+    fn tick(&mut self, items: &Items, recipes: &Recipes, ticks: u32) -> bool {
+        let smelting = self.smelting(items, recipes);
+
+        if self.burn_time == 0 {
+            // Fuel is only used when there is something to smelt.
+            let (Some(burn_time), Some(_)) = (burn_time(items, &self.fuel), &smelting) else {
+                return std::mem::take(&mut self.cook_progress) != 0;
+            };
+            self.fuel.take(1);
+            self.burn_time = ((burn_time * TICKS_PER_SECOND as f32) as u32).max(1);
+            self.burn_duration = self.burn_time;
+        }
+
+        self.burn_time = self.burn_time.saturating_sub(ticks);
+
+        let Some((mut output, cook_time)) = smelting else {
+            self.cook_progress = 0;
+            return true;
+        };
+
+        self.cook_progress += ticks;
+        if self.cook_progress >= cook_time {
+            self.cook_progress = 0;
+            self.input.take(1);
+            if self.output.is_empty() {
+                self.output = output;
+            } else {
+                output.transfer_to(&mut self.output, u32::MAX);
+            }
+        }
+
+        true
+    }
+
+    fn progress_text(&self, items: &Items, recipes: &Recipes) -> String {
+        let cooking = match self.smelting(items, recipes) {
+            Some((_, cook_time)) => self.cook_progress * 100 / cook_time,
+            None => 0,
+        };
+        let fuel = (self.burn_time * 100)
+            .checked_div(self.burn_duration)
+            .unwrap_or(0);
+        format!("Smelting: {}%  Fuel: {}%", cooking, fuel)
+    }
+}
+
+// How many seconds the item burns for when used as fuel.
+fn burn_time(items: &Items, item_stack: &ItemStack) -> Option<f32> {
+    let item = item_stack.item()?;
+    items.get_config(&item.id).properties["burn_time"]
+        .as_f64()
+        .map(|burn_time| burn_time as f32)
+}
+
+#[derive(Component)]
+struct FurnaceTicks;
+
+fn register_furnace_ticks(
+    mut commands: Commands,
+    mut block_tick_registry: ResMut<BlockTickRegistry>,
+) {
+    let entity = commands.spawn((BlockTicks::default(), FurnaceTicks)).id();
+    block_tick_registry.insert(Blocks::get().get_id("furnace"), entity);
+}
+
+fn tick_furnaces(
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    block_entities: Res<BlockEntities>,
+    mut scheduled_ticks: ResMut<ScheduledTicks>,
+    mut furnace_ticks: Query<&mut BlockTicks, With<FurnaceTicks>>,
+    mut furnaces: Query<(&BlockEntity, &mut Furnace)>,
+) {
+    let mut block_ticks = furnace_ticks.single_mut();
+    for position in block_ticks.read() {
+        let Some((block_entity, mut furnace)) = block_entities
+            .get(&position)
+            .and_then(|entity| furnaces.get_mut(entity).ok())
+        else {
+            continue;
+        };
+
+        // Only mark the furnace as changed when it is, so idle furnaces aren't saved or sent to
+        // players.
+        if furnace
+            .bypass_change_detection()
+            .tick(&items, &recipes, FURNACE_TICK_INTERVAL)
+        {
+            furnace.set_changed();
+        }
+
+        // Furnaces keep themselves ticking for as long as they have something to do.
+        if furnace.is_active(&items, &recipes) {
+            scheduled_ticks.schedule(
+                block_entity.position(),
+                block_entity.block_id(),
+                FURNACE_TICK_INTERVAL,
+                0,
+            );
+        }
+    }
+}
+
+// Starts furnaces ticking when items are moved in or their chunk is loaded.
+fn schedule_furnace_ticks(
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    mut scheduled_ticks: ResMut<ScheduledTicks>,
+    furnaces: Query<(&BlockEntity, &Furnace), Changed<Furnace>>,
+) {
+    for (block_entity, furnace) in furnaces.iter() {
+        if furnace.is_active(&items, &recipes) {
+            scheduled_ticks.schedule(
+                block_entity.position(),
+                block_entity.block_id(),
+                FURNACE_TICK_INTERVAL,
+                0,
+            );
+        }
+    }
+}
+
+/// The furnace whose interface the player has open.
+#[derive(Component)]
+struct OpenFurnace(Entity);
+
+fn open_furnaces(
+    mut commands: Commands,
+    net: Res<Server>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    mut furnaces: Query<(Entity, &Furnace, &mut HandInteractions)>,
+    hotbars: Query<&Hotbar>,
+) {
+    for (furnace_entity, furnace, mut interactions) in furnaces.iter_mut() {
+        for player_entity in interactions.read() {
+            let Ok(hotbar) = hotbars.get(player_entity) else {
+                continue;
+            };
+
+            commands
+                .entity(player_entity)
+                .insert(OpenFurnace(furnace_entity));
+
+            send_furnace(&net, player_entity, furnace, &items, &recipes);
+            net.send_one(player_entity, item_box_update("furnace/hotbar", hotbar));
+            net.send_one(
+                player_entity,
+                messages::InterfaceVisibilityUpdate {
+                    interface_path: "furnace".to_owned(),
+                    visible: true,
+                },
+            );
+        }
+    }
+}
+
+fn close_furnaces(
+    mut commands: Commands,
+    net: Res<Server>,
+    furnaces: Query<(), With<Furnace>>,
+    mut players: Query<
+        (
+            Entity,
+            &OpenFurnace,
+            &mut Hotbar,
+            &mut Inventory,
+            &mut HeldInterfaceStack,
+            &GlobalTransform,
+        ),
+        With<Player>,
+    >,
+    mut close_events: EventReader<NetworkMessage<messages::InterfaceClose>>,
+) {
+    let mut closed: Vec<Entity> = close_events
+        .read()
+        .filter(|close_event| close_event.interface_path == "furnace")
+        .map(|close_event| close_event.player_entity)
+        .collect();
+
+    // The furnace has been broken or unloaded while it was open.
+    for (player_entity, open_furnace, ..) in players.iter() {
+        if furnaces.get(open_furnace.0).is_err() {
+            net.send_one(
+                player_entity,
+                messages::InterfaceVisibilityUpdate {
+                    interface_path: "furnace".to_owned(),
+                    visible: false,
+                },
+            );
+            closed.push(player_entity);
+        }
+    }
+
+    for player_entity in closed {
+        let Ok((_, _, mut hotbar, mut inventory, mut held_stack, transform)) =
+            players.get_mut(player_entity)
+        else {
+            continue;
+        };
+
+        commands.entity(player_entity).remove::<OpenFurnace>();
+
+        return_held_stack(
+            &mut commands,
+            &mut hotbar,
+            &mut inventory,
+            &mut held_stack,
+            transform.translation(),
+        );
+    }
+}
+
+fn move_items(
+    net: Res<Server>,
+    items: Res<Items>,
+    // Broken furnaces drop their items, they can't be taken as well.
+    mut furnaces: Query<&mut Furnace, Without<Removed>>,
+    mut players: Query<(&OpenFurnace, &mut Hotbar, &mut HeldInterfaceStack), With<Player>>,
+    mut take_events: EventReader<NetworkMessage<messages::InterfaceTakeItem>>,
+    mut place_events: EventReader<NetworkMessage<messages::InterfacePlaceItem>>,
+) {
+    let item_moves = read_item_moves(&mut take_events, &mut place_events, "furnace/");
+
+    for (player_entity, mut item_moves) in item_moves {
+        let Ok((open_furnace, mut hotbar, mut held_stack)) = players.get_mut(player_entity) else {
+            continue;
+        };
+
+        let Ok(mut furnace) = furnaces.get_mut(open_furnace.0) else {
+            continue;
+        };
+
+        while let Some(item_move) = item_moves.next(&held_stack) {
+            // Only fuel can go in the fuel slot, and nothing in the output.
+            let placeable = match item_move.interface_path.as_str() {
+                "furnace/output" => false,
+                "furnace/fuel" => burn_time(&items, &held_stack).is_some(),
+                _ => true,
+            };
+
+            let item_stack = match item_move.interface_path.as_str() {
+                "furnace/input" if item_move.index == 0 => Some(&mut furnace.input),
+                "furnace/fuel" if item_move.index == 0 => Some(&mut furnace.fuel),
+                "furnace/output" if item_move.index == 0 => Some(&mut furnace.output),
+                "furnace/hotbar" => hotbar.get_mut(item_move.index),
+                _ => None,
+            };
+
+            let Some(item_stack) = item_stack else {
+                net.disconnect(player_entity);
+                break;
+            };
+
+            if (!item_move.is_take && !placeable) || !item_move.apply(item_stack, &mut held_stack) {
+                // Triggers change detection so both interfaces are sent again.
+                furnace.set_changed();
+                hotbar.set_changed();
+            }
+        }
+    }
+}
+
+fn send_furnace(
+    net: &Server,
+    player_entity: Entity,
+    furnace: &Furnace,
+    items: &Items,
+    recipes: &Recipes,
+) {
+    net.send_one(
+        player_entity,
+        item_box_update("furnace/input", std::slice::from_ref(&furnace.input)),
+    );
+    net.send_one(
+        player_entity,
+        item_box_update("furnace/fuel", std::slice::from_ref(&furnace.fuel)),
+    );
+    net.send_one(
+        player_entity,
+        item_box_update("furnace/output", std::slice::from_ref(&furnace.output)),
+    );
+    net.send_one(
+        player_entity,
+        messages::InterfaceTextUpdate {
+            interface_path: "furnace/progress".to_owned(),
+            index: 0,
+            text: furnace.progress_text(items, recipes),
+            font_size: CHAT_FONT_SIZE,
+            color: CHAT_TEXT_COLOR.to_owned(),
+        },
+    );
+}
+
+fn send_furnace_updates(
+    net: Res<Server>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    furnaces: Query<Ref<Furnace>>,
+    players: Query<(Entity, &OpenFurnace, Ref<Hotbar>)>,
+) {
+    for (player_entity, open_furnace, hotbar) in players.iter() {
+        let Ok(furnace) = furnaces.get(open_furnace.0) else {
+            continue;
+        };
+
+        // Everyone that has the furnace open sees the progress.
+        if furnace.is_changed() {
+            send_furnace(&net, player_entity, &furnace, &items, &recipes);
+        }
+
+        if hotbar.is_changed() {
+            net.send_one(player_entity, item_box_update("furnace/hotbar", &hotbar));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use fmc::{
+    blocks::{BlockPosition, Blocks},
+    prelude::*,
+    utils::Rng,
+    world::{BlockUpdate, WorldMap},
+};
+
+use super::{RandomTick, ServerBlockConfigs};
+
+// How far above a block is looked at to know if it can see the sky.
+const SKY_DISTANCE: i32 = 32;
+
+pub(super) struct GrassPlugin;
+impl Plugin for GrassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, spread_grass);
+    }
+}
+
+// Grass turns back into dirt when something solid that doesn't let light through is placed on top
+// of it. Otherwise it spreads to dirt close by that isn't covered and that can see the sky.
+fn spread_grass(
+    world_map: Res<WorldMap>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    mut random_ticks: EventReader<RandomTick>,
+    mut block_updates: EventWriter<BlockUpdate>,
+    mut rng: Local<Rng>,
+) {
+    let blocks = Blocks::get();
+    let grass = blocks.get_id("grass");
+    let dirt = blocks.get_id("dirt");
+
+    // Blocks that are not loaded are assumed to let the light through.
+    let blocks_light = |position: BlockPosition| {
+        world_map.get_block(position).is_some_and(|block_id| {
+            blocks.get_config(&block_id).is_solid()
+                && !server_block_configs.get(&block_id).transparent
+        })
+    };
+
+    let is_covered = |position: BlockPosition| blocks_light(position + IVec3::Y);
+
+    let sees_sky = |position: BlockPosition| {
+        (1..=SKY_DISTANCE).all(|height| !blocks_light(position + IVec3::Y * height))
+    };
+
+    for random_tick in random_ticks.read() {
+        if random_tick.block_id != grass {
+            continue;
+        }
+
+        if is_covered(random_tick.position) {
+            block_updates.send(BlockUpdate::Replace {
+                position: random_tick.position,
+                block_id: dirt,
+                block_state: None,
+                block_data: None,
+            });
+            continue;
+        }
+
+        // Can spread one block sideways, and from three blocks below to one above.
+        let mut random =
+            |min: i32, max: i32| min + (rng.next_f32() * (max - min + 1) as f32) as i32;
+        let position =
+            random_tick.position + IVec3::new(random(-1, 1), random(-3, 1), random(-1, 1));
+
+        if world_map.get_block(position) == Some(dirt)
+            && !is_covered(position)
+            && sees_sky(position)
+        {
+            block_updates.send(BlockUpdate::Replace {
+                position,
+                block_id: grass,
+                block_state: None,
+                block_data: None,
+            });
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use fmc::{
+    bevy::math::DVec3,
+    blocks::{BlockFace, BlockPosition, Blocks},
+    items::{ItemStack, Items},
+    prelude::*,
+    utils::Rng,
+    world::{BlockUpdate, ChangedBlockEvent, WorldMap},
+};
+
+use crate::items::DroppedItem;
+
+use super::{player_placed::track_player_placed_blocks, PlayerPlacedBlocks, ServerBlockConfigs};
+
+/// Chance each second that a leaf that has lost its support decays.
+const DECAY_CHANCE: f32 = 0.4;
+/// Chance that a decayed leaf drops an item.
+const DROP_CHANCE: f32 = 0.1;
+/// Chance that a decayed leaf drops its 'decay_drop', e.g. an apple.
+const DECAY_DROP_CHANCE: f32 = 0.05;
+
+pub(super) struct LeafDecayPlugin;
+impl Plugin for LeafDecayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DecayingLeaves::default()).add_systems(
+            Update,
+            // Placed leaves must be known to be player placed before they are looked at.
+            (find_decaying_leaves, decay_leaves)
+                .chain()
+                .after(track_player_placed_blocks),
+        );
+    }
+}
+
+/// Leaves that are too far from a trunk, they are removed randomly over time.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct DecayingLeaves(HashSet<BlockPosition>);
+
+const NEIGHBOURS: [BlockFace; 6] = [
+    BlockFace::Top,
+    BlockFace::Bottom,
+    BlockFace::Front,
+    BlockFace::Back,
+    BlockFace::Left,
+    BlockFace::Right,
+];
+
+fn find_decaying_leaves(
+    world_map: Res<WorldMap>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    player_placed_blocks: Res<PlayerPlacedBlocks>,
+    mut decaying_leaves: ResMut<DecayingLeaves>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+) {
+    for changed_block in changed_blocks.read() {
+        // Leaves can only lose their support when a supporting block or a leaf is removed.
+        let from = server_block_configs.get(&changed_block.from.0);
+        if !from.supports_leaves && from.decay_distance.is_none() {
+            continue;
+        }
+
+        if changed_block.to.0 == changed_block.from.0
+            || server_block_configs
+                .get(&changed_block.to.0)
+                .supports_leaves
+        {
+            continue;
+        }
+
+        // Any leaves next to the changed block may have been held up by it.
+        for block_face in NEIGHBOURS {
+            let position = block_face.shift_position(changed_block.position);
+            if decaying_leaves.contains(&position) {
+                continue;
+            }
+
+            let Some(decay_distance) = world_map
+                .get_block(position)
+                .and_then(|block_id| server_block_configs.get(&block_id).decay_distance)
+            else {
+                continue;
+            };
+
+            for (leaf_position, distance) in
+                distance_to_support(&world_map, &server_block_configs, position, decay_distance)
+            {
+                if distance > decay_distance && !player_placed_blocks.contains(&leaf_position) {
+                    decaying_leaves.insert(leaf_position);
+                }
+            }
+        }
+    }
+}
+
+// Finds the leaves within 'decay_distance' steps of the start position and how many steps they
+// are from a supporting block, u32::MAX if there is none. A leaf is supported through a path of
+// at most 'decay_distance' leaves, so every leaf on such a path is within twice that of the
+// start. All of those are searched, and the distances spread out from the supporting blocks.
+fn distance_to_support(
+    world_map: &WorldMap,
+    server_block_configs: &ServerBlockConfigs,
+    start: BlockPosition,
+    decay_distance: u32,
+) -> Vec<(BlockPosition, u32)> {
+    let is_leaf = |position: BlockPosition| {
+        world_map
+            .get_block(position)
+            .is_some_and(|block_id| server_block_configs.get(&block_id).decay_distance.is_some())
+    };
+    let is_support = |position: BlockPosition| {
+        world_map
+            .get_block(position)
+            .is_some_and(|block_id| server_block_configs.get(&block_id).supports_leaves)
+    };
+
+    // Leaves connected to the start position, and how many steps away they are.
+    let mut region = HashMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(position) = queue.pop_front() {
+        let steps = region[&position];
+        if steps == decay_distance * 2 {
+            continue;
+        }
+
+        for block_face in NEIGHBOURS {
+            let neighbour = block_face.shift_position(position);
+            if !region.contains_key(&neighbour) && is_leaf(neighbour) {
+                region.insert(neighbour, steps + 1);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    let mut distances: HashMap<BlockPosition, u32> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for position in region.keys() {
+        if NEIGHBOURS
+            .iter()
+            .any(|block_face| is_support(block_face.shift_position(*position)))
+        {
+            distances.insert(*position, 1);
+            queue.push_back(*position);
+        }
+    }
+
+    while let Some(position) = queue.pop_front() {
+        let distance = distances[&position];
+        for block_face in NEIGHBOURS {
+            let neighbour = block_face.shift_position(position);
+            if region.contains_key(&neighbour) && !distances.contains_key(&neighbour) {
+                distances.insert(neighbour, distance + 1);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    region
+        .into_iter()
+        .filter(|(_, steps)| *steps <= decay_distance)
+        .map(|(position, _)| {
+            (
+                position,
+                distances.get(&position).copied().unwrap_or(u32::MAX),
+            )
+        })
+        .collect()
+}
+
+fn decay_leaves(
+    mut commands: Commands,
+    time: Res<Time>,
+    items: Res<Items>,
+    world_map: Res<WorldMap>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    player_placed_blocks: Res<PlayerPlacedBlocks>,
+    mut decaying_leaves: ResMut<DecayingLeaves>,
+    mut block_updates: EventWriter<BlockUpdate>,
+    mut rng: Local<Rng>,
+) {
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+    let chance = DECAY_CHANCE * time.delta_secs();
+
+    decaying_leaves.retain(|position| {
+        let Some(block_id) = world_map.get_block(*position) else {
+            // Wait for the chunk to be loaded again
+            return true;
+        };
+
+        // It has been replaced by something else
+        let Some(decay_distance) = server_block_configs.get(&block_id).decay_distance else {
+            return false;
+        };
+
+        if player_placed_blocks.contains(position) {
+            return false;
+        }
+
+        if rng.next_f32() > chance {
+            return true;
+        }
+
+        // A supporting block may have been placed since it was found.
+        let supported =
+            distance_to_support(&world_map, &server_block_configs, *position, decay_distance)
+                .into_iter()
+                .any(|(leaf_position, distance)| {
+                    leaf_position == *position && distance <= decay_distance
+                });
+        if supported {
+            return false;
+        }
+
+        block_updates.send(BlockUpdate::Replace {
+            position: *position,
+            block_id: air,
+            block_state: None,
+            block_data: None,
+        });
+
+        let mut drops = Vec::new();
+        if rng.next_f32() < DROP_CHANCE {
+            drops.extend(blocks.get_config(&block_id).drop(None));
+        }
+        if let Some(decay_drop) = &server_block_configs.get(&block_id).decay_drop {
+            if rng.next_f32() < DECAY_DROP_CHANCE {
+                drops.push((items.get_id(decay_drop).unwrap(), 1));
+            }
+        }
+
+        for (item_id, count) in drops {
+            commands.spawn((
+                DroppedItem::new(ItemStack::new(items.get_config(&item_id), count)),
+                Transform::from_translation(position.as_dvec3() + DVec3::splat(0.5)),
+            ));
+        }
+
+        false
+    });
+}
@@ -1,10 +1,39 @@
 use fmc::prelude::*;
 
-mod water;
+mod block_entities;
+mod chest;
+mod falling;
+mod fluids;
+mod furnace;
+mod grass;
+mod leaves;
+mod player_placed;
+mod properties;
+mod random_ticks;
+mod scheduled_ticks;
+
+pub use block_entities::{BlockEntities, BlockEntity, BlockEntityData, BlockEntityDataPlugin};
+pub use fluids::{Fluids, UnsettledFluids};
+pub use player_placed::PlayerPlacedBlocks;
+pub use properties::{ServerBlockConfig, ServerBlockConfigs, SERVER_BLOCK_CONFIG_PATH};
+pub use random_ticks::RandomTick;
+pub use scheduled_ticks::{
+    BlockTickRegistry, BlockTickSystems, BlockTicks, ScheduledTicks, TICKS_PER_SECOND,
+};
 
 pub(super) struct BlocksPlugin;
 impl Plugin for BlocksPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(water::WaterPlugin);
+        app.add_plugins(properties::BlockPropertiesPlugin)
+            .add_plugins(player_placed::PlayerPlacedPlugin)
+            .add_plugins(block_entities::BlockEntityPlugin)
+            .add_plugins(random_ticks::RandomTickPlugin)
+            .add_plugins(scheduled_ticks::ScheduledTickPlugin)
+            .add_plugins(chest::ChestPlugin)
+            .add_plugins(furnace::FurnacePlugin)
+            .add_plugins(fluids::FluidPlugin)
+            .add_plugins(falling::FallingBlocksPlugin)
+            .add_plugins(leaves::LeafDecayPlugin)
+            .add_plugins(grass::GrassPlugin);
     }
 }
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use fmc::{blocks::BlockPosition, database::Database, prelude::*, world::ChangedBlockEvent};
+
+pub(super) struct PlayerPlacedPlugin;
+impl Plugin for PlayerPlacedPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_player_placed_blocks)
+            .add_systems(Update, track_player_placed_blocks);
+    }
+}
+
+/// Remembers which blocks were placed by players, so they can be treated differently from
+/// generated ones. Only blocks that need it are tracked, e.g. leaves so they don't decay.
+#[derive(Resource, Default)]
+pub struct PlayerPlacedBlocks {
+    positions: HashSet<BlockPosition>,
+    // Blocks that have been placed, but the change has not been applied yet.
+    placed: HashSet<BlockPosition>,
+}
+
+impl PlayerPlacedBlocks {
+    /// Mark the block that is about to be placed at the position as placed by a player. It stays
+    /// marked until the block is changed.
+    pub fn insert(&mut self, position: BlockPosition) {
+        self.placed.insert(position);
+    }
+
+    pub fn contains(&self, position: &BlockPosition) -> bool {
+        self.positions.contains(position)
+    }
+}
+
+fn load_player_placed_blocks(mut commands: Commands, database: Res<Database>) {
+    let conn = database.get_write_connection();
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS player_placed_blocks (
+            x INTEGER NOT NULL,
+            y INTEGER NOT NULL,
+            z INTEGER NOT NULL,
+            PRIMARY KEY (x, y, z)
+        )",
+        [],
+    )
+    .unwrap();
+
+    let mut stmt = conn
+        .prepare("SELECT x, y, z FROM player_placed_blocks")
+        .unwrap();
+    let positions = stmt
+        .query_map([], |row| {
+            Ok(BlockPosition::new(row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap()
+        .map(|position| position.unwrap())
+        .collect();
+
+    commands.insert_resource(PlayerPlacedBlocks {
+        positions,
+        placed: HashSet::new(),
+    });
+}
+
+pub(super) fn track_player_placed_blocks(
+    database: Res<Database>,
+    mut player_placed_blocks: ResMut<PlayerPlacedBlocks>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+) {
+    if changed_blocks.is_empty() {
+        return;
+    }
+
+    let conn = database.get_write_connection();
+
+    for changed_block in changed_blocks.read() {
+        let position = changed_block.position;
+
+        if player_placed_blocks.placed.remove(&position) {
+            player_placed_blocks.positions.insert(position);
+            conn.execute(
+                "INSERT OR REPLACE INTO player_placed_blocks VALUES (?,?,?)",
+                [position.x, position.y, position.z],
+            )
+            .unwrap();
+        } else if player_placed_blocks.positions.remove(&position) {
+            conn.execute(
+                "DELETE FROM player_placed_blocks WHERE x = ? AND y = ? AND z = ?",
+                [position.x, position.y, position.z],
+            )
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use fmc::{
+    blocks::{BlockId, Blocks, BLOCK_CONFIG_PATH},
+    items::{Items, ITEM_CONFIG_PATH},
+    prelude::*,
+};
+use serde::Deserialize;
+
+pub const SERVER_BLOCK_CONFIG_PATH: &str = "./assets/server/blocks/";
+
+pub(super) struct BlockPropertiesPlugin;
+impl Plugin for BlockPropertiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_server_block_configs);
+    }
+}
+
+/// Block configuration that is only of interest to the server. It is kept apart from the block
+/// configs at BLOCK_CONFIG_PATH since those are sent to the clients. Each file is named after the
+/// block it configures, blocks without a file use the default values.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ServerBlockConfig {
+    /// The block falls when there is nothing to hold it up
+    #[serde(default)]
+    pub gravity: bool,
+    /// The block decays when it is further than this many blocks from a block that
+    /// 'supports_leaves', counting only steps through other blocks that decay.
+    #[serde(default)]
+    pub decay_distance: Option<u32>,
+    /// Keeps leaves from decaying, e.g. tree trunks.
+    #[serde(default)]
+    pub supports_leaves: bool,
+    /// Light passes through the block even though it is solid, e.g. glass and leaves.
+    #[serde(default)]
+    pub transparent: bool,
+    /// Item that is sometimes dropped when the block decays, e.g. fruit from leaves.
+    #[serde(default)]
+    pub decay_drop: Option<String>,
+    /// The block receives random ticks, see 'RandomTick'
+    #[serde(default)]
+    pub random_tick: bool,
+    /// Tools must be of at least this tier for the block to drop anything, the tier of a tool is
+    /// set by the "tier" property of the item.
+    #[serde(default)]
+    pub tool_tier: u32,
+    /// Name of the block that is placed instead when the block is placed in the upper half of a
+    /// block space, e.g. slabs and stairs. Rotation is kept.
+    #[serde(default)]
+    pub upper_half: Option<String>,
+}
+
+#[derive(Resource, Default)]
+pub struct ServerBlockConfigs {
+    configs: HashMap<BlockId, ServerBlockConfig>,
+    default: ServerBlockConfig,
+}
+
+impl ServerBlockConfigs {
+    pub fn get(&self, block_id: &BlockId) -> &ServerBlockConfig {
+        self.configs.get(block_id).unwrap_or(&self.default)
+    }
+
+    /// Iterate over the blocks that have a server config
+    pub fn iter(&self) -> impl Iterator<Item = (&BlockId, &ServerBlockConfig)> {
+        self.configs.iter()
+    }
+}
+
+pub(super) fn load_server_block_configs(
+    mut commands: Commands,
+    blocks: Res<Blocks>,
+    items: Res<Items>,
+) {
+    let mut server_block_configs = ServerBlockConfigs::default();
+
+    let directory = match std::fs::read_dir(SERVER_BLOCK_CONFIG_PATH) {
+        Ok(dir) => dir,
+        // The directory is optional
+        Err(_) => {
+            commands.insert_resource(server_block_configs);
+            return;
+        }
+    };
+
+    for dir_entry in directory {
+        let file_path = dir_entry.unwrap().path();
+        let Some(block_name) = file_path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+
+        if !blocks.contains_block(block_name) {
+            panic!(
+                "Startup failed while loading the server block configs. The file at '{}' \
+                configures a block with the name '{}', but no block by that name exists. Make sure \
+                a block by the same name is present at '{}'",
+                file_path.display(),
+                block_name,
+                BLOCK_CONFIG_PATH
+            );
+        }
+
+        let file = std::fs::File::open(&file_path).unwrap();
+        let config: ServerBlockConfig = serde_json::from_reader(file).unwrap_or_else(|e| {
+            panic!(
+                "Failed to read the server block config at '{}'.\nError: {}",
+                file_path.display(),
+                e
+            )
+        });
+
+        if let Some(upper_half) = &config.upper_half {
+            if !blocks.contains_block(upper_half) {
+                panic!(
+                    "Startup failed while loading the server block configs. The block '{}' has \
+                    '{}' as its upper half, but no block by that name exists. Make sure a block \
+                    by the same name is present at '{}'",
+                    block_name, upper_half, BLOCK_CONFIG_PATH
+                );
+            }
+        }
+
+        if let Some(decay_drop) = &config.decay_drop {
+            if items.get_id(decay_drop).is_none() {
+                panic!(
+                    "Startup failed while loading the server block configs. The block '{}' \
+                    drops '{}' when it decays, but no item by that name exists. Make sure an \
+                    item by the same name is present at '{}'",
+                    block_name, decay_drop, ITEM_CONFIG_PATH
+                );
+            }
+        }
+
+        server_block_configs
+            .configs
+            .insert(blocks.get_id(block_name), config);
+    }
+
+    commands.insert_resource(server_block_configs);
+}
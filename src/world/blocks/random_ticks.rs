@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+
+use fmc::{
+    blocks::{BlockId, BlockPosition},
+    players::Player,
+    prelude::*,
+    utils::Rng,
+    world::{
+        chunk::{Chunk, ChunkPosition},
+        WorldMap,
+    },
+};
+
+use crate::settings::Settings;
+
+use super::ServerBlockConfigs;
+
+/// Chunks this many chunks away from a player receive random ticks.
+const RANDOM_TICK_DISTANCE: i32 = 4;
+
+pub(super) struct RandomTickPlugin;
+impl Plugin for RandomTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RandomTick>()
+            .add_systems(Update, send_random_ticks);
+    }
+}
+
+/// Sent for random blocks in the chunks around the players. Only blocks that have opted in
+/// through 'random_tick' in their server block config receive them. Used for things that
+/// should happen slowly and unpredictably, like grass spreading.
+#[derive(Event)]
+pub struct RandomTick {
+    pub position: BlockPosition,
+    pub block_id: BlockId,
+}
+
+fn send_random_ticks(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    world_map: Res<WorldMap>,
+    server_block_configs: Res<ServerBlockConfigs>,
+    players: Query<&GlobalTransform, With<Player>>,
+    mut random_ticks: EventWriter<RandomTick>,
+    mut accumulated: Local<f32>,
+    mut rng: Local<Rng>,
+) {
+    *accumulated += settings.random_ticks_per_chunk as f32 * time.delta_secs();
+    let tick_count = accumulated.floor() as u32;
+    if tick_count == 0 {
+        return;
+    }
+    *accumulated -= tick_count as f32;
+
+    let mut chunk_positions = HashSet::new();
+    for transform in players.iter() {
+        let player_chunk = ChunkPosition::from(transform.translation());
+        for x in -RANDOM_TICK_DISTANCE..=RANDOM_TICK_DISTANCE {
+            for y in -RANDOM_TICK_DISTANCE..=RANDOM_TICK_DISTANCE {
+                for z in -RANDOM_TICK_DISTANCE..=RANDOM_TICK_DISTANCE {
+                    let offset = IVec3::new(x, y, z) * Chunk::SIZE as i32;
+                    chunk_positions.insert(player_chunk + offset);
+                }
+            }
+        }
+    }
+
+    for chunk_position in chunk_positions {
+        let origin = BlockPosition::from(chunk_position);
+        for _ in 0..tick_count {
+            let offset = IVec3::new(
+                (rng.next_f32() * Chunk::SIZE as f32) as i32,
+                (rng.next_f32() * Chunk::SIZE as f32) as i32,
+                (rng.next_f32() * Chunk::SIZE as f32) as i32,
+            );
+            let position = origin + offset;
+
+            // Also skips chunks that aren't loaded
+            let Some(block_id) = world_map.get_block(position) else {
+                break;
+            };
+
+            if server_block_configs.get(&block_id).random_tick {
+                random_ticks.send(RandomTick { position, block_id });
+            }
+        }
+    }
+}
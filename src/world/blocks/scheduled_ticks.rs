@@ -0,0 +1,366 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    time::Duration,
+};
+
+use fmc::{
+    bevy::app::AppExit,
+    blocks::{BlockId, BlockPosition, Blocks},
+    database::Database,
+    prelude::*,
+    world::{chunk::ChunkPosition, ChunkSubscriptions},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// How many block ticks there are in a second.
+pub const TICKS_PER_SECOND: u32 = 20;
+
+pub(super) struct ScheduledTickPlugin;
+impl Plugin for ScheduledTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BlockTickRegistry::default())
+            .add_systems(Startup, setup)
+            .add_systems(Update, deliver_scheduled_ticks.in_set(BlockTickSystems))
+            .add_systems(Last, save_on_exit);
+    }
+}
+
+/// Systems that handle block ticks should run after this set to avoid 1-frame lag.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct BlockTickSystems;
+
+/// Maps blocks to the entity that handles their scheduled ticks. The entity must have a
+/// 'BlockTicks' component.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct BlockTickRegistry(HashMap<BlockId, Entity>);
+
+/// List of block positions whose scheduled tick has come due during the last tick.
+#[derive(Component, Default)]
+pub struct BlockTicks(Vec<BlockPosition>);
+
+impl BlockTicks {
+    pub fn read(&mut self) -> impl Iterator<Item = BlockPosition> + '_ {
+        self.0.drain(..)
+    }
+}
+
+struct ScheduledTick {
+    due: u64,
+    priority: i32,
+    // Order the tick was scheduled in, keeps ticks with the same due time and priority in order.
+    order: u64,
+    position: BlockPosition,
+    block_id: BlockId,
+}
+
+// Reversed so that the BinaryHeap pops the tick that is due first.
+impl Ord for ScheduledTick {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .due
+            .cmp(&self.due)
+            .then(other.priority.cmp(&self.priority))
+            .then(other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for ScheduledTick {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ScheduledTick {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScheduledTick {}
+
+/// The format the scheduled ticks of a chunk are saved as in the database.
+#[derive(Serialize, Deserialize)]
+struct ScheduledTickSave {
+    position: BlockPosition,
+    // Stored by name as block ids can change between runs.
+    block: String,
+    // Ticks left until it is due
+    remaining: u64,
+    priority: i32,
+}
+
+/// Ticks scheduled for blocks, e.g. water that should flow after a delay. When a tick comes due
+/// it is given to the entity registered for the block in the 'BlockTickRegistry'. The handler
+/// is responsible for checking that the block is still there if it matters.
+///
+/// Ticks in chunks no player is subscribed to are paused and stored in the database until the
+/// chunk is subscribed to again. The rest are stored when the server shuts down.
+#[derive(Resource)]
+pub struct ScheduledTicks {
+    current_tick: u64,
+    // Time left over from the last tick
+    elapsed: Duration,
+    queue: BinaryHeap<ScheduledTick>,
+    // Used to avoid scheduling the same tick twice.
+    scheduled: HashSet<(BlockPosition, BlockId)>,
+    // Chunks that have ticks stored in the database.
+    saved: HashSet<ChunkPosition>,
+    // How many ticks are queued in each chunk
+    chunks: HashMap<ChunkPosition, usize>,
+    order: u64,
+}
+
+impl ScheduledTicks {
+    /// Schedule a tick for the block at the position after 'delay' ticks. Ticks that come due at
+    /// the same time are delivered in order of priority, lowest first. Nothing happens if the
+    /// block already has a tick scheduled at the position.
+    pub fn schedule(
+        &mut self,
+        position: BlockPosition,
+        block_id: BlockId,
+        delay: u32,
+        priority: i32,
+    ) {
+        if !self.scheduled.insert((position, block_id)) {
+            return;
+        }
+
+        *self
+            .chunks
+            .entry(ChunkPosition::from(position))
+            .or_default() += 1;
+
+        self.order += 1;
+        self.queue.push(ScheduledTick {
+            due: self.current_tick + delay.max(1) as u64,
+            priority,
+            order: self.order,
+            position,
+            block_id,
+        });
+    }
+
+    pub fn is_scheduled(&self, position: BlockPosition, block_id: BlockId) -> bool {
+        self.scheduled.contains(&(position, block_id))
+    }
+
+    // Forget a tick that has been taken out of the queue.
+    fn unschedule(&mut self, tick: &ScheduledTick) {
+        self.scheduled.remove(&(tick.position, tick.block_id));
+
+        let chunk_position = ChunkPosition::from(tick.position);
+        if let Some(count) = self.chunks.get_mut(&chunk_position) {
+            *count -= 1;
+            if *count == 0 {
+                self.chunks.remove(&chunk_position);
+            }
+        }
+    }
+
+    // Moves all the ticks of the chunks into the database.
+    fn save_chunks(&mut self, chunk_positions: &HashSet<ChunkPosition>, database: &Database) {
+        // Bring back what is already stored so it isn't overwritten.
+        for chunk_position in chunk_positions.iter() {
+            if self.saved.contains(chunk_position) {
+                self.load_chunk(*chunk_position, database);
+            }
+        }
+
+        let mut saves: HashMap<ChunkPosition, Vec<ScheduledTickSave>> = HashMap::new();
+        let mut queue = Vec::with_capacity(self.queue.len());
+        let blocks = Blocks::get();
+
+        for tick in std::mem::take(&mut self.queue).into_vec() {
+            let chunk_position = ChunkPosition::from(tick.position);
+            if !chunk_positions.contains(&chunk_position) {
+                queue.push(tick);
+                continue;
+            }
+
+            self.unschedule(&tick);
+            saves
+                .entry(chunk_position)
+                .or_default()
+                .push(ScheduledTickSave {
+                    position: tick.position,
+                    block: blocks.get_config(&tick.block_id).name.clone(),
+                    remaining: tick.due.saturating_sub(self.current_tick),
+                    priority: tick.priority,
+                });
+        }
+
+        self.queue = BinaryHeap::from(queue);
+
+        let conn = database.get_write_connection();
+        let mut stmt = conn
+            .prepare("INSERT OR REPLACE INTO scheduled_ticks VALUES (?,?,?,?)")
+            .unwrap();
+        for (chunk_position, save) in saves {
+            stmt.execute(rusqlite::params![
+                chunk_position.x,
+                chunk_position.y,
+                chunk_position.z,
+                serde_json::to_string(&save).unwrap()
+            ])
+            .unwrap();
+            self.saved.insert(chunk_position);
+        }
+    }
+
+    // Loads the saved ticks of a chunk and removes them from the database.
+    fn load_chunk(&mut self, chunk_position: ChunkPosition, database: &Database) {
+        self.saved.remove(&chunk_position);
+
+        let conn = database.get_write_connection();
+        let data: String = match conn.query_row(
+            "SELECT data FROM scheduled_ticks WHERE x = ? AND y = ? AND z = ?",
+            [chunk_position.x, chunk_position.y, chunk_position.z],
+            |row| row.get(0),
+        ) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+
+        conn.execute(
+            "DELETE FROM scheduled_ticks WHERE x = ? AND y = ? AND z = ?",
+            [chunk_position.x, chunk_position.y, chunk_position.z],
+        )
+        .unwrap();
+
+        let save: Vec<ScheduledTickSave> = serde_json::from_str(&data).unwrap();
+        let blocks = Blocks::get();
+        for tick in save {
+            // The block may have been removed since it was saved
+            if !blocks.contains_block(&tick.block) {
+                continue;
+            }
+
+            self.schedule(
+                tick.position,
+                blocks.get_id(&tick.block),
+                tick.remaining as u32,
+                tick.priority,
+            );
+        }
+    }
+}
+
+fn setup(mut commands: Commands, database: Res<Database>) {
+    let conn = database.get_write_connection();
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS scheduled_ticks (
+            x INTEGER NOT NULL,
+            y INTEGER NOT NULL,
+            z INTEGER NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (x, y, z)
+        )",
+        [],
+    )
+    .unwrap();
+
+    let mut stmt = conn.prepare("SELECT x, y, z FROM scheduled_ticks").unwrap();
+    let saved = stmt
+        .query_map([], |row| {
+            Ok(ChunkPosition::new(row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap()
+        .map(|chunk_position| chunk_position.unwrap())
+        .collect();
+
+    commands.insert_resource(ScheduledTicks {
+        current_tick: 0,
+        elapsed: Duration::ZERO,
+        queue: BinaryHeap::new(),
+        scheduled: HashSet::new(),
+        saved,
+        chunks: HashMap::new(),
+        order: 0,
+    });
+}
+
+fn has_subscribers(
+    chunk_subscriptions: &ChunkSubscriptions,
+    chunk_position: &ChunkPosition,
+) -> bool {
+    chunk_subscriptions
+        .get_subscribers(chunk_position)
+        .is_some_and(|subscribers| !subscribers.is_empty())
+}
+
+fn deliver_scheduled_ticks(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    block_tick_registry: Res<BlockTickRegistry>,
+    mut scheduled_ticks: ResMut<ScheduledTicks>,
+    mut block_ticks: Query<&mut BlockTicks>,
+) {
+    // Pause the chunks that are no longer subscribed to
+    let unsubscribed: HashSet<ChunkPosition> = scheduled_ticks
+        .chunks
+        .keys()
+        .filter(|chunk_position| !has_subscribers(&chunk_subscriptions, chunk_position))
+        .copied()
+        .collect();
+    if !unsubscribed.is_empty() {
+        scheduled_ticks.save_chunks(&unsubscribed, &database);
+    }
+
+    // Resume the chunks that have been subscribed to again
+    let resumed: Vec<ChunkPosition> = scheduled_ticks
+        .saved
+        .iter()
+        .filter(|chunk_position| has_subscribers(&chunk_subscriptions, chunk_position))
+        .copied()
+        .collect();
+    for chunk_position in resumed {
+        scheduled_ticks.load_chunk(chunk_position, &database);
+    }
+
+    let tick_length = Duration::from_secs(1) / TICKS_PER_SECOND;
+    scheduled_ticks.elapsed += time.delta();
+    while scheduled_ticks.elapsed >= tick_length {
+        scheduled_ticks.elapsed -= tick_length;
+        scheduled_ticks.current_tick += 1;
+    }
+
+    let mut budget = settings.scheduled_ticks_per_tick;
+
+    while budget > 0 {
+        if scheduled_ticks
+            .queue
+            .peek()
+            .is_none_or(|tick| tick.due > scheduled_ticks.current_tick)
+        {
+            break;
+        }
+        let tick = scheduled_ticks.queue.pop().unwrap();
+        scheduled_ticks.unschedule(&tick);
+        budget -= 1;
+
+        let Some(entity) = block_tick_registry.get(&tick.block_id) else {
+            continue;
+        };
+        if let Ok(mut ticks) = block_ticks.get_mut(*entity) {
+            ticks.0.push(tick.position);
+        }
+    }
+}
+
+fn save_on_exit(
+    database: Res<Database>,
+    mut scheduled_ticks: ResMut<ScheduledTicks>,
+    mut exit_events: EventReader<AppExit>,
+) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+
+    let chunk_positions = scheduled_ticks.chunks.keys().copied().collect();
+    scheduled_ticks.save_chunks(&chunk_positions, &database);
+}
@@ -11,11 +11,13 @@ use crate::settings::Settings;
 mod biomes;
 pub mod blocks;
 mod terrain_generation;
+mod world_edit;
 
 pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(blocks::BlocksPlugin)
+            .add_plugins(world_edit::WorldEditPlugin)
             .add_systems(Startup, setup)
             .add_systems(
                 Update,
@@ -29,6 +31,7 @@ fn setup(
     database: Res<Database>,
     blocks: Res<Blocks>,
     settings: Res<Settings>,
+    unsettled_fluids: Res<blocks::UnsettledFluids>,
 ) {
     let properties = WorldProperties::load(database).unwrap_or(WorldProperties::default());
     commands.insert_resource(properties);
@@ -36,6 +39,7 @@ fn setup(
     commands.insert_resource(WorldMap::new(terrain_generation::Earth::new(
         settings.seed,
         &blocks,
+        unsettled_fluids.clone(),
     )));
 }
 
@@ -1,5 +1,5 @@
 use fmc::{
-    blocks::Blocks,
+    blocks::{BlockId, BlockPosition, Blocks},
     noise::{Frequency, Noise},
     // noise::Noise,
     prelude::*,
@@ -11,7 +11,7 @@ use fmc::{
 
 use rand::SeedableRng;
 
-use super::biomes::Biomes;
+use super::{biomes::Biomes, blocks::UnsettledFluids};
 
 pub struct Earth {
     biomes: Biomes,
@@ -20,6 +20,7 @@ pub struct Earth {
     terrain_shape: Noise,
     caves: Noise,
     seed: u64,
+    unsettled_fluids: UnsettledFluids,
 }
 
 impl TerrainGenerator for Earth {
@@ -54,6 +55,7 @@ impl TerrainGenerator for Earth {
 
             //self.carve_caves(chunk_position, &mut chunk);
             self.generate_features(chunk_position, &mut chunk);
+            self.find_unsettled_fluids(chunk_position, &chunk);
         }
 
         return chunk;
@@ -61,7 +63,7 @@ impl TerrainGenerator for Earth {
 }
 
 impl Earth {
-    pub fn new(seed: u64, blocks: &Blocks) -> Self {
+    pub fn new(seed: u64, blocks: &Blocks, unsettled_fluids: UnsettledFluids) -> Self {
         let freq = 1.0 / 2f32.powi(9) * 3.0;
         // let freq = 0.00305;
         let continents = Noise::perlin(Frequency {
@@ -186,6 +188,7 @@ impl Earth {
             terrain_shape,
             caves,
             seed,
+            unsettled_fluids,
         }
     }
 
@@ -349,6 +352,46 @@ impl Earth {
             });
     }
 
+    // Water that borders air after caves and blueprints have been placed should flow when the
+    // chunk is loaded. Every chunk is inserted, even without any unsettled fluids, as the blocks
+    // on its border are checked against the neighbouring chunks once it is loaded.
+    fn find_unsettled_fluids(&self, chunk_position: ChunkPosition, chunk: &Chunk) {
+        if chunk.is_uniform() {
+            self.unsettled_fluids.insert(chunk_position, Vec::new());
+            return;
+        }
+
+        let biome = self.biomes.get_biome();
+        let is_water =
+            |block: BlockId| block == biome.surface_liquid || block == biome.sub_surface_liquid;
+
+        let mut unsettled = Vec::new();
+        for x in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                for z in 0..Chunk::SIZE {
+                    if !is_water(chunk[[x, y, z]]) {
+                        continue;
+                    }
+
+                    // Neighbours outside the chunk are left to the border check.
+                    if (x > 0 && chunk[[x - 1, y, z]] == biome.air)
+                        || (x + 1 < Chunk::SIZE && chunk[[x + 1, y, z]] == biome.air)
+                        || (z > 0 && chunk[[x, y, z - 1]] == biome.air)
+                        || (z + 1 < Chunk::SIZE && chunk[[x, y, z + 1]] == biome.air)
+                        || (y > 0 && chunk[[x, y - 1, z]] == biome.air)
+                    {
+                        unsettled.push(
+                            BlockPosition::from(chunk_position)
+                                + BlockPosition::new(x as i32, y as i32, z as i32),
+                        );
+                    }
+                }
+            }
+        }
+
+        self.unsettled_fluids.insert(chunk_position, unsettled);
+    }
+
     fn generate_features(&self, chunk_position: ChunkPosition, chunk: &mut Chunk) {
         let blocks = Blocks::get();
         let surface_blocks = [blocks.get_id("grass")];
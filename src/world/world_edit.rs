@@ -0,0 +1,545 @@
+use std::collections::VecDeque;
+
+use fmc::{
+    blocks::{BlockId, BlockPosition, BlockState, Blocks},
+    networking::{NetworkMessage, Server},
+    players::{Player, Target, Targets},
+    prelude::*,
+    protocol::messages,
+    world::{BlockUpdate, WorldMap},
+};
+
+use crate::{
+    chat::{CHAT_FONT_SIZE, CHAT_TEXT_COLOR},
+    players::GameMode,
+};
+
+/// Max amount of blocks that are changed each tick, split evenly between the players that have
+/// edits in progress. Large edits are spread out over several ticks so they don't freeze the
+/// server.
+const MAX_BLOCKS_PER_TICK: usize = 4096;
+/// Largest region that can be edited in one operation.
+const MAX_VOLUME: usize = 1_000_000;
+/// How many operations that can be undone.
+const MAX_HISTORY: usize = 10;
+
+pub(super) struct WorldEditPlugin;
+impl Plugin for WorldEditPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                add_world_editor,
+                handle_edit_commands,
+                apply_edits.after(handle_edit_commands),
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BlockChange {
+    position: BlockPosition,
+    block_id: BlockId,
+    block_state: Option<BlockState>,
+}
+
+/// The positions of a box of blocks, iterated one at a time so large regions don't have to be
+/// held in memory.
+#[derive(Clone, Copy)]
+struct Region {
+    min: BlockPosition,
+    size: IVec3,
+    index: usize,
+}
+
+impl Region {
+    // The box between the two corners, inclusive.
+    fn new(pos1: BlockPosition, pos2: BlockPosition) -> Result<Self, String> {
+        let min = BlockPosition::new(pos1.x.min(pos2.x), pos1.y.min(pos2.y), pos1.z.min(pos2.z));
+        let max = BlockPosition::new(pos1.x.max(pos2.x), pos1.y.max(pos2.y), pos1.z.max(pos2.z));
+
+        let region = Region {
+            min,
+            size: *max - *min + IVec3::ONE,
+            index: 0,
+        };
+        if region.volume() > MAX_VOLUME {
+            return Err(format!(
+                "The region is too large, {} blocks, the max is {}",
+                region.volume(),
+                MAX_VOLUME
+            ));
+        }
+
+        Ok(region)
+    }
+
+    fn volume(&self) -> usize {
+        self.size.x as usize * self.size.y as usize * self.size.z as usize
+    }
+}
+
+impl Iterator for Region {
+    type Item = BlockPosition;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.volume() {
+            return None;
+        }
+
+        let index = self.index as i32;
+        self.index += 1;
+
+        let x = index / (self.size.y * self.size.z);
+        let y = index / self.size.z % self.size.y;
+        let z = index % self.size.z;
+        Some(self.min + IVec3::new(x, y, z))
+    }
+}
+
+enum Edit {
+    /// Set all blocks in the region, used by //set and //replace
+    Fill {
+        region: Region,
+        block_id: BlockId,
+        /// Only blocks of this type are changed, used by //replace
+        mask: Option<BlockId>,
+    },
+    /// Used by //paste and //undo
+    Changes(VecDeque<BlockChange>),
+    /// Copy the region to the clipboard, with positions relative to the origin.
+    Copy {
+        region: Region,
+        origin: BlockPosition,
+        copied: Vec<BlockChange>,
+    },
+}
+
+struct EditJob {
+    edit: Edit,
+    /// The blocks that have been overwritten by the job, used to undo it.
+    replaced: Vec<BlockChange>,
+    /// If the job should be added to the undo history when it finishes.
+    record_history: bool,
+}
+
+impl EditJob {
+    fn new(edit: Edit) -> Self {
+        Self {
+            edit,
+            replaced: Vec::new(),
+            record_history: true,
+        }
+    }
+}
+
+/// Per player world edit state
+#[derive(Component, Default)]
+struct WorldEditor {
+    pos1: Option<BlockPosition>,
+    pos2: Option<BlockPosition>,
+    /// Copied blocks, positions are relative to where the player stood when copying.
+    clipboard: Vec<BlockChange>,
+    jobs: VecDeque<EditJob>,
+    history: VecDeque<Vec<BlockChange>>,
+}
+
+impl WorldEditor {
+    fn region(&self) -> Result<Region, String> {
+        let (Some(pos1), Some(pos2)) = (self.pos1, self.pos2) else {
+            return Err("Both //pos1 and //pos2 must be set first".to_owned());
+        };
+
+        Region::new(pos1, pos2)
+    }
+
+    // Add the blocks replaced by an edit to the undo history, forgetting the oldest edit when it
+    // is full.
+    fn record_history(&mut self, replaced: Vec<BlockChange>) {
+        self.history.push_back(replaced);
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+    }
+}
+
+fn add_world_editor(mut commands: Commands, new_players: Query<Entity, Added<Player>>) {
+    for player_entity in new_players.iter() {
+        commands
+            .entity(player_entity)
+            .insert(WorldEditor::default());
+    }
+}
+
+fn send_reply(net: &Server, player_entity: Entity, text: String) {
+    net.send_one(
+        player_entity,
+        messages::InterfaceTextUpdate {
+            interface_path: "chat/history".to_owned(),
+            index: i32::MAX,
+            text,
+            font_size: CHAT_FONT_SIZE,
+            color: CHAT_TEXT_COLOR.to_owned(),
+        },
+    );
+}
+
+fn parse_block(name: &str) -> Result<BlockId, String> {
+    let blocks = Blocks::get();
+    if !blocks.contains_block(name) {
+        return Err(format!("There is no block called '{}'", name));
+    }
+    Ok(blocks.get_id(name))
+}
+
+fn handle_edit_commands(
+    net: Res<Server>,
+    mut player_query: Query<
+        (&mut WorldEditor, &GameMode, &Targets, &GlobalTransform),
+        With<Player>,
+    >,
+    mut chat_messages: EventReader<NetworkMessage<messages::InterfaceTextInput>>,
+) {
+    for chat_message in chat_messages.read() {
+        if &chat_message.interface_path != "chat/input" {
+            continue;
+        }
+
+        let Some(command) = chat_message.text.strip_prefix("//") else {
+            continue;
+        };
+
+        let Ok((mut editor, game_mode, targets, transform)) =
+            player_query.get_mut(chat_message.player_entity)
+        else {
+            continue;
+        };
+
+        if *game_mode != GameMode::Creative {
+            send_reply(
+                &net,
+                chat_message.player_entity,
+                "World edit is only available in creative mode".to_owned(),
+            );
+            continue;
+        }
+
+        let player_position = BlockPosition::from(transform.translation());
+
+        let mut arguments = command.split_whitespace();
+        let result = match arguments.next() {
+            Some(name @ ("pos1" | "pos2")) => {
+                // Use the block the player is looking at, or where they stand if there is none.
+                let position = match targets.get_first_block(|_| true) {
+                    Some(Target::Block { block_position, .. }) => *block_position,
+                    _ => player_position,
+                };
+
+                if name == "pos1" {
+                    editor.pos1 = Some(position);
+                } else {
+                    editor.pos2 = Some(position);
+                }
+
+                Ok(format!(
+                    "Set {} to ({}, {}, {})",
+                    name, position.x, position.y, position.z
+                ))
+            }
+            Some("set") => match arguments.next().map(parse_block) {
+                Some(Ok(block_id)) => editor.region().map(|region| {
+                    editor.jobs.push_back(EditJob::new(Edit::Fill {
+                        region,
+                        block_id,
+                        mask: None,
+                    }));
+                    format!("Setting {} blocks", region.volume())
+                }),
+                Some(Err(e)) => Err(e),
+                None => Err("Usage: //set <block>".to_owned()),
+            },
+            Some("replace") => match (
+                arguments.next().map(parse_block),
+                arguments.next().map(parse_block),
+            ) {
+                (Some(Ok(from)), Some(Ok(to))) => editor.region().map(|region| {
+                    editor.jobs.push_back(EditJob::new(Edit::Fill {
+                        region,
+                        block_id: to,
+                        mask: Some(from),
+                    }));
+                    "Replacing blocks".to_owned()
+                }),
+                (Some(Err(e)), _) | (_, Some(Err(e))) => Err(e),
+                _ => Err("Usage: //replace <from block> <to block>".to_owned()),
+            },
+            Some("copy") => editor.region().map(|region| {
+                editor.jobs.push_back(EditJob::new(Edit::Copy {
+                    region,
+                    origin: player_position,
+                    copied: Vec::new(),
+                }));
+                format!("Copying {} blocks", region.volume())
+            }),
+            Some("paste") => {
+                if editor.clipboard.is_empty() {
+                    Err("Nothing has been copied".to_owned())
+                } else {
+                    let changes = editor
+                        .clipboard
+                        .iter()
+                        .map(|change| BlockChange {
+                            position: player_position + change.position,
+                            ..*change
+                        })
+                        .collect();
+                    editor.jobs.push_back(EditJob::new(Edit::Changes(changes)));
+                    Ok(format!("Pasting {} blocks", editor.clipboard.len()))
+                }
+            }
+            Some("undo") => {
+                if let Some(replaced) = editor.history.pop_back() {
+                    let message = format!("Undoing {} block changes", replaced.len());
+                    // Restore in reverse order so that blocks changed several times end up
+                    // as they were originally.
+                    let mut job = EditJob::new(Edit::Changes(replaced.into_iter().rev().collect()));
+                    job.record_history = false;
+                    editor.jobs.push_back(job);
+                    Ok(message)
+                } else {
+                    Err("Nothing to undo".to_owned())
+                }
+            }
+            _ => Err("Unknown command, available commands are: \
+                //pos1, //pos2, //set, //replace, //copy, //paste, //undo"
+                .to_owned()),
+        };
+
+        match result {
+            Ok(text) | Err(text) => send_reply(&net, chat_message.player_entity, text),
+        }
+    }
+}
+
+// Each player's edits are applied in order, each block that is looked at counts against the
+// player's share of the budget whether it is changed or not.
+fn apply_edits(
+    net: Res<Server>,
+    world_map: Res<WorldMap>,
+    mut editors: Query<(Entity, &mut WorldEditor)>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let editing = editors
+        .iter()
+        .filter(|(_, editor)| !editor.jobs.is_empty())
+        .count();
+    if editing == 0 {
+        return;
+    }
+    let budget_per_player = (MAX_BLOCKS_PER_TICK / editing).max(1);
+
+    for (player_entity, mut editor) in editors.iter_mut() {
+        let mut budget = budget_per_player;
+        // Block updates are applied to the world map after this tick, copying the blocks before
+        // then would copy what was there before the update.
+        let mut has_updates = false;
+
+        while budget > 0 {
+            let Some(job) = editor.jobs.front_mut() else {
+                break;
+            };
+
+            if has_updates && matches!(job.edit, Edit::Copy { .. }) {
+                break;
+            }
+
+            let mut finished = false;
+            while budget > 0 && !finished {
+                budget -= 1;
+
+                match &mut job.edit {
+                    Edit::Fill {
+                        region,
+                        block_id,
+                        mask,
+                    } => {
+                        let Some(position) = region.next() else {
+                            finished = true;
+                            continue;
+                        };
+                        let change = BlockChange {
+                            position,
+                            block_id: *block_id,
+                            block_state: None,
+                        };
+                        has_updates |= apply_change(
+                            change,
+                            *mask,
+                            &world_map,
+                            &mut job.replaced,
+                            &mut block_updates,
+                        );
+                    }
+                    Edit::Changes(changes) => {
+                        let Some(change) = changes.pop_front() else {
+                            finished = true;
+                            continue;
+                        };
+                        has_updates |= apply_change(
+                            change,
+                            None,
+                            &world_map,
+                            &mut job.replaced,
+                            &mut block_updates,
+                        );
+                    }
+                    Edit::Copy {
+                        region,
+                        origin,
+                        copied,
+                    } => {
+                        let Some(position) = region.next() else {
+                            finished = true;
+                            continue;
+                        };
+                        // Blocks in chunks that aren't loaded can't be copied.
+                        let Some(block_id) = world_map.get_block(position) else {
+                            continue;
+                        };
+                        copied.push(BlockChange {
+                            position: position - *origin,
+                            block_id,
+                            block_state: world_map.get_block_state(position),
+                        });
+                    }
+                }
+            }
+
+            if !finished {
+                continue;
+            }
+
+            let job = editor.jobs.pop_front().unwrap();
+            if let Edit::Copy { copied, .. } = job.edit {
+                send_reply(
+                    &net,
+                    player_entity,
+                    format!("Copied {} blocks", copied.len()),
+                );
+                editor.clipboard = copied;
+            } else if job.record_history && !job.replaced.is_empty() {
+                editor.record_history(job.replaced);
+            }
+        }
+    }
+}
+
+// Replace the block, remembering what was there so it can be undone. Returns true if the block
+// was changed.
+fn apply_change(
+    change: BlockChange,
+    mask: Option<BlockId>,
+    world_map: &WorldMap,
+    replaced: &mut Vec<BlockChange>,
+    block_updates: &mut EventWriter<BlockUpdate>,
+) -> bool {
+    let Some(current_block) = world_map.get_block(change.position) else {
+        // Can't edit chunks that aren't loaded
+        return false;
+    };
+
+    if mask.is_some_and(|mask| mask != current_block) {
+        return false;
+    }
+
+    let current_state = world_map.get_block_state(change.position);
+    if current_block == change.block_id && current_state == change.block_state {
+        return false;
+    }
+
+    replaced.push(BlockChange {
+        position: change.position,
+        block_id: current_block,
+        block_state: current_state,
+    });
+
+    block_updates.send(BlockUpdate::Replace {
+        position: change.position,
+        block_id: change.block_id,
+        block_state: change.block_state,
+        block_data: None,
+    });
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn region_visits_every_block_once() {
+        let region =
+            Region::new(BlockPosition::new(1, -2, 3), BlockPosition::new(2, 0, 6)).unwrap();
+        assert_eq!(region.volume(), 2 * 3 * 4);
+
+        let positions: Vec<BlockPosition> = region.collect();
+        assert_eq!(positions.len(), region.volume());
+
+        let unique: HashSet<BlockPosition> = positions.iter().copied().collect();
+        assert_eq!(unique.len(), positions.len());
+
+        for position in positions {
+            assert!((1..=2).contains(&position.x));
+            assert!((-2..=0).contains(&position.y));
+            assert!((3..=6).contains(&position.z));
+        }
+    }
+
+    #[test]
+    fn region_corners_can_be_in_any_order() {
+        let a = Region::new(BlockPosition::new(5, 5, 5), BlockPosition::new(0, 7, 2)).unwrap();
+        let b = Region::new(BlockPosition::new(0, 5, 2), BlockPosition::new(5, 7, 5)).unwrap();
+        assert!(a.eq(b));
+    }
+
+    #[test]
+    fn single_block_region() {
+        let position = BlockPosition::new(3, 4, 5);
+        let region = Region::new(position, position).unwrap();
+        assert_eq!(region.collect::<Vec<_>>(), vec![position]);
+    }
+
+    #[test]
+    fn region_volume_is_limited() {
+        // 100x100x100 is exactly the max
+        let max = Region::new(BlockPosition::new(0, 0, 0), BlockPosition::new(99, 99, 99));
+        assert_eq!(max.unwrap().volume(), MAX_VOLUME);
+
+        let too_large = Region::new(BlockPosition::new(0, 0, 0), BlockPosition::new(99, 99, 100));
+        assert!(too_large.is_err());
+    }
+
+    #[test]
+    fn history_forgets_oldest_edits() {
+        let mut editor = WorldEditor::default();
+        for i in 0..MAX_HISTORY + 2 {
+            editor.record_history(vec![BlockChange {
+                position: BlockPosition::new(i as i32, 0, 0),
+                block_id: 0,
+                block_state: None,
+            }]);
+        }
+
+        assert_eq!(editor.history.len(), MAX_HISTORY);
+        // The first two edits were dropped
+        assert_eq!(editor.history.front().unwrap()[0].position.x, 2);
+        assert_eq!(
+            editor.history.back().unwrap()[0].position.x,
+            MAX_HISTORY as i32 + 1
+        );
+    }
+}